@@ -0,0 +1,112 @@
+use crate::ray::Ray;
+use crate::vec::*;
+
+// An axis-aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    // the box spanned by two corners, given in any order
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Self {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    // grows degenerate (flat) axes slightly so the slab test stays robust
+    pub fn padded(self) -> Self {
+        const MIN_EXTENT: f64 = 1e-6;
+        let pad = |min: f64, max: f64| {
+            if max - min < MIN_EXTENT {
+                (min - MIN_EXTENT / 2.0, max + MIN_EXTENT / 2.0)
+            } else {
+                (min, max)
+            }
+        };
+        let (x0, x1) = pad(self.min.x(), self.max.x());
+        let (y0, y1) = pad(self.min.y(), self.max.y());
+        let (z0, z1) = pad(self.min.z(), self.max.z());
+        Self {
+            min: Point3::new(x0, y0, z0),
+            max: Point3::new(x1, y1, z1),
+        }
+    }
+
+    pub fn union(self, rhs: Self) -> Self {
+        Self {
+            min: self.min.min(rhs.min),
+            max: self.max.max(rhs.max),
+        }
+    }
+
+    pub fn centroid(self) -> Point3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn extent(self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn longest_axis(self) -> usize {
+        let extent = self.extent();
+        if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(self) -> f64 {
+        let e = self.extent();
+        2.0 * (e.x() * e.y() + e.y() * e.z() + e.z() * e.x())
+    }
+
+    // the box bounding this box after a transform
+    pub fn transformed(self, transform: Transform) -> Self {
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = -min;
+        for corner in 0..8 {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    self.min.axis(axis)
+                } else {
+                    self.max.axis(axis)
+                }
+            };
+            let point = transform.point(Point3::new(pick(0), pick(1), pick(2)));
+            min = min.min(point);
+            max = max.max(point);
+        }
+        Self { min, max }
+    }
+
+    // slab test: whether the ray passes through the box anywhere in (t_min, t_max)
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.interval(ray, t_min, t_max).is_some()
+    }
+
+    // the part of (t_min, t_max) in which the ray is inside the box, if any
+    pub fn interval(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction.axis(axis);
+            let origin = ray.origin.axis(axis);
+            let mut t0 = (self.min.axis(axis) - origin) * inv_d;
+            let mut t1 = (self.max.axis(axis) - origin) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
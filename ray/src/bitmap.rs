@@ -1,5 +1,8 @@
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
 use crate::vec::*;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 
 // A Bitmap image.
 //
@@ -16,12 +19,44 @@ pub struct Bitmap {
     data: Vec<Color>,
 }
 
-trait Serializer {
-    fn save(bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()>;
+// An image file format that bitmaps can be written in
+pub trait Serializer {
+    fn save(&self, bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()>;
 }
 
+// ASCII (P3) portable pixmap
 pub struct PPM;
 
+// Portable Network Graphics, in 8 or 16 bits per channel
+pub struct PNG {
+    pub depth: BitDepth,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+// Portable float map: linear, unclamped 32 bit floats
+pub struct PFM;
+
+// OpenEXR, uncompressed scanlines of linear, unclamped half or full floats. Besides the main image
+// (channels R, G and B), any number of extra named layers of the same size can be stored, as
+// channels `<name>.R`, `<name>.G` and `<name>.B`.
+pub struct EXR<'a> {
+    pub pixel_type: PixelType,
+    pub layers: Vec<(&'a str, &'a Bitmap)>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum PixelType {
+    #[default]
+    Half,
+    Float,
+}
+
 impl Bitmap {
     pub fn new(size: Size) -> Self {
         Self {
@@ -50,6 +85,283 @@ impl Bitmap {
         self.data[Self::index(self.size.width, self.size.height, x, y)] = color;
     }
 
+    // Loads a PNG or PPM (P3 or P6) image, or a Radiance HDR (.hdr) image. The stored values of
+    // PNG and PPM images are taken to be gamma-encoded the same way the renderer encodes its
+    // output, and are decoded back to linear; HDR images are already linear.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let reader = BufReader::new(file);
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let bitmap = match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("png") => Self::decode_png(reader),
+            Some("ppm") => Self::decode_ppm(reader),
+            Some("hdr") => Self::decode_hdr(reader),
+            _ => bail!("unsupported image format"),
+        };
+        bitmap.with_context(|| format!("failed to load {}", path.display()))
+    }
+
+    // builds a bitmap from rows of linear values, given top to bottom
+    fn from_rows(size: Size, rows: impl Iterator<Item = Vec<Color>>) -> Self {
+        let mut bitmap = Self::new(size);
+        for (row, colors) in rows.enumerate() {
+            let y = size.height - row as u32 - 1;
+            for (x, color) in colors.into_iter().enumerate() {
+                bitmap.set(x as u32, y, color);
+            }
+        }
+        bitmap
+    }
+
+    // builds a bitmap from rows of gamma-encoded values in [0, 1], given top to bottom
+    fn from_encoded_rows(size: Size, rows: impl Iterator<Item = Vec<Color>>) -> Self {
+        let rows = rows.map(|row| row.into_iter().map(|color| color * color).collect());
+        Self::from_rows(size, rows)
+    }
+
+    fn decode_png(reader: impl BufRead + std::io::Seek) -> Result<Self> {
+        let mut decoder = png::Decoder::new(reader);
+        // unpack palettes and sub-byte depths, keeping 16 bit samples
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let mut data = vec![0; reader.output_buffer_size().context("image too large")?];
+        let info = reader.next_frame(&mut data)?;
+        let data = &data[..info.buffer_size()];
+
+        let channels = info.color_type.samples();
+        let (bytes, max) = match info.bit_depth {
+            png::BitDepth::Sixteen => (2, u16::MAX as f64),
+            _ => (1, u8::MAX as f64),
+        };
+        let sample = |i: usize| {
+            let value = match bytes {
+                2 => u16::from_be_bytes([data[2 * i], data[2 * i + 1]]) as f64,
+                _ => data[i] as f64,
+            };
+            value / max
+        };
+        let size = Size::new(info.width, info.height);
+        let rows = (0..info.height as usize).map(|row| {
+            (0..info.width as usize)
+                .map(|x| {
+                    let i = (row * info.width as usize + x) * channels;
+                    match info.color_type {
+                        // alpha is ignored
+                        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                            Color::new(sample(i), sample(i), sample(i))
+                        }
+                        _ => Color::new(sample(i), sample(i + 1), sample(i + 2)),
+                    }
+                })
+                .collect()
+        });
+        Ok(Self::from_encoded_rows(size, rows))
+    }
+
+    fn decode_ppm(mut reader: impl BufRead) -> Result<Self> {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        // the header is whitespace-separated tokens, with comments running to the end of a line
+        let mut pos = 0;
+        let mut token = || -> Result<String> {
+            loop {
+                while pos < data.len() && data[pos].is_ascii_whitespace() {
+                    pos += 1;
+                }
+                if pos < data.len() && data[pos] == b'#' {
+                    while pos < data.len() && data[pos] != b'\n' {
+                        pos += 1;
+                    }
+                    continue;
+                }
+                break;
+            }
+            let start = pos;
+            while pos < data.len() && !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if start == pos {
+                bail!("unexpected end of file");
+            }
+            Ok(String::from_utf8_lossy(&data[start..pos]).into_owned())
+        };
+        let number = |token: String| -> Result<u32> {
+            token
+                .parse()
+                .with_context(|| format!("invalid number `{token}`"))
+        };
+
+        let magic = token()?;
+        let width = number(token()?)?;
+        let height = number(token()?)?;
+        let max = number(token()?)?;
+        if max == 0 || max > u16::MAX as u32 {
+            bail!("invalid maximum value {max}");
+        }
+        if width == 0 || height == 0 {
+            bail!("empty image");
+        }
+        let count = width as usize * height as usize * 3;
+
+        let samples: Vec<u32> = match magic.as_str() {
+            "P3" => (0..count)
+                .map(|_| number(token()?))
+                .collect::<Result<_>>()?,
+            "P6" => {
+                // a single whitespace character separates the header from the data
+                let start = pos + 1;
+                let bytes = if max < 256 { 1 } else { 2 };
+                let end = start + count * bytes;
+                if end > data.len() {
+                    bail!("unexpected end of file");
+                }
+                data[start..end]
+                    .chunks(bytes)
+                    .map(|chunk| match chunk {
+                        [a, b] => u16::from_be_bytes([*a, *b]) as u32,
+                        [a] => *a as u32,
+                        _ => unreachable!(),
+                    })
+                    .collect()
+            }
+            _ => bail!("unsupported PPM variant {magic}"),
+        };
+
+        let size = Size::new(width, height);
+        let max = max as f64;
+        let rows = samples.chunks(width as usize * 3).map(|row| {
+            row.chunks(3)
+                .map(|rgb| {
+                    Color::new(
+                        rgb[0] as f64 / max,
+                        rgb[1] as f64 / max,
+                        rgb[2] as f64 / max,
+                    )
+                })
+                .collect()
+        });
+        Ok(Self::from_encoded_rows(size, rows))
+    }
+
+    // Radiance RGBE, with flat or run-length encoded scanlines, in the standard -Y H +X W
+    // orientation
+    fn decode_hdr(mut reader: impl BufRead) -> Result<Self> {
+        let mut line = String::new();
+        let mut read_line = |line: &mut String| -> Result<()> {
+            line.clear();
+            if reader.read_line(line)? == 0 {
+                bail!("unexpected end of file");
+            }
+            Ok(())
+        };
+
+        read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            bail!("missing Radiance signature");
+        }
+        // header variables, up to an empty line
+        loop {
+            read_line(&mut line)?;
+            let variable = line.trim();
+            if variable.is_empty() {
+                break;
+            }
+            if let Some(format) = variable.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    bail!("unsupported pixel format {format}");
+                }
+            }
+        }
+        read_line(&mut line)?;
+        let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", height, "+X", width] => (height.parse::<u32>(), width.parse::<u32>()),
+            _ => bail!("unsupported orientation `{}`", line.trim()),
+        };
+        let (height, width) = (
+            height.context("invalid height")?,
+            width.context("invalid width")?,
+        );
+        if width == 0 || height == 0 {
+            bail!("empty image");
+        }
+
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        let mut pos = 0;
+        let mut rows = Vec::with_capacity(height as usize);
+        for _ in 0..height {
+            let (scanline, read) = Self::decode_hdr_scanline(&data[pos..], width as usize)?;
+            pos += read;
+            let colors = scanline
+                .chunks(4)
+                .map(|rgbe| {
+                    if rgbe[3] == 0 {
+                        return Color::ZERO;
+                    }
+                    // the shared exponent is biased by 128, and the mantissas are 8 bit fractions
+                    let scale = 2f64.powi(rgbe[3] as i32 - 136);
+                    Color::new(
+                        (rgbe[0] as f64 + 0.5) * scale,
+                        (rgbe[1] as f64 + 0.5) * scale,
+                        (rgbe[2] as f64 + 0.5) * scale,
+                    )
+                })
+                .collect();
+            rows.push(colors);
+        }
+        Ok(Self::from_rows(Size::new(width, height), rows.into_iter()))
+    }
+
+    // decodes one scanline of RGBE quadruples, returning it with the number of bytes read
+    fn decode_hdr_scanline(data: &[u8], width: usize) -> Result<(Vec<u8>, usize)> {
+        let truncated = || anyhow!("unexpected end of file");
+        let is_rle = (8..0x8000).contains(&width)
+            && data.len() >= 4
+            && data[0] == 2
+            && data[1] == 2
+            && data[2] & 0x80 == 0;
+        if !is_rle {
+            let bytes = data.get(..4 * width).ok_or_else(truncated)?;
+            return Ok((bytes.to_vec(), 4 * width));
+        }
+        if ((data[2] as usize) << 8 | data[3] as usize) != width {
+            bail!("scanline width mismatch");
+        }
+
+        // the four components are stored one after the other, each as runs and literal spans
+        let mut scanline = vec![0; 4 * width];
+        let mut pos = 4;
+        for component in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = *data.get(pos).ok_or_else(truncated)? as usize;
+                pos += 1;
+                let (count, run) = if count > 128 {
+                    (count - 128, true)
+                } else {
+                    (count, false)
+                };
+                if count == 0 || x + count > width {
+                    bail!("invalid run length");
+                }
+                for i in 0..count {
+                    let byte = if run {
+                        *data.get(pos).ok_or_else(truncated)?
+                    } else {
+                        *data.get(pos + i).ok_or_else(truncated)?
+                    };
+                    scanline[4 * (x + i) + component] = byte;
+                }
+                pos += if run { 1 } else { count };
+                x += count;
+            }
+        }
+        Ok((scanline, pos))
+    }
+
     #[inline(always)]
     fn index(width: u32, height: u32, x: u32, mut y: u32) -> usize {
         y = height - y - 1;
@@ -57,8 +369,8 @@ impl Bitmap {
     }
 }
 
-impl PPM {
-    pub fn save(self, bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()> {
+impl Serializer for PPM {
+    fn save(&self, bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()> {
         write!(
             target,
             "P3\n{} {}\n255\n",
@@ -67,9 +379,9 @@ impl PPM {
         for y in (0..bitmap.size.height).rev() {
             for x in 0..bitmap.size.width {
                 let color = bitmap.get(x, y);
-                write!(
+                writeln!(
                     target,
-                    "{} {} {}\n",
+                    "{} {} {}",
                     Self::to_256(color.r()),
                     Self::to_256(color.g()),
                     Self::to_256(color.b())
@@ -79,9 +391,185 @@ impl PPM {
 
         Result::Ok(())
     }
+}
 
+impl PPM {
     #[inline]
     fn to_256(v: f64) -> i64 {
         (256.0 * v.clamp(0.0, 0.999)).floor() as i64
     }
 }
+
+impl PNG {
+    pub fn new(depth: BitDepth) -> Self {
+        Self { depth }
+    }
+}
+
+impl Serializer for PNG {
+    fn save(&self, bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()> {
+        let mut encoder = png::Encoder::new(target, bitmap.size.width, bitmap.size.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(match self.depth {
+            BitDepth::Eight => png::BitDepth::Eight,
+            BitDepth::Sixteen => png::BitDepth::Sixteen,
+        });
+
+        // rows go top to bottom, 16 bit samples are big-endian
+        let mut data = Vec::with_capacity(bitmap.data.len() * 3 * 2);
+        for y in (0..bitmap.size.height).rev() {
+            for x in 0..bitmap.size.width {
+                let color = bitmap.get(x, y);
+                for v in [color.r(), color.g(), color.b()] {
+                    match self.depth {
+                        BitDepth::Eight => data.push(PPM::to_256(v) as u8),
+                        BitDepth::Sixteen => {
+                            let v = (65536.0 * v.clamp(0.0, 0.99999)).floor() as u16;
+                            data.extend_from_slice(&v.to_be_bytes());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+        Result::Ok(())
+    }
+}
+
+impl Serializer for PFM {
+    fn save(&self, bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()> {
+        // a negative scale marks the data as little-endian
+        write!(
+            target,
+            "PF\n{} {}\n-1.0\n",
+            bitmap.size.width, bitmap.size.height
+        )?;
+        // rows go bottom to top
+        for y in 0..bitmap.size.height {
+            for x in 0..bitmap.size.width {
+                let color = bitmap.get(x, y);
+                for v in [color.r(), color.g(), color.b()] {
+                    target.write_all(&(v as f32).to_le_bytes())?;
+                }
+            }
+        }
+
+        Result::Ok(())
+    }
+}
+
+impl<'a> EXR<'a> {
+    pub fn new(pixel_type: PixelType) -> Self {
+        Self {
+            pixel_type,
+            layers: vec![],
+        }
+    }
+
+    pub fn with_layer(mut self, name: &'a str, bitmap: &'a Bitmap) -> Self {
+        self.layers.push((name, bitmap));
+        self
+    }
+
+    fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        for s in [name, kind] {
+            header.extend_from_slice(s.as_bytes());
+            header.push(0);
+        }
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+}
+
+impl Serializer for EXR<'_> {
+    fn save(&self, bitmap: &Bitmap, target: &mut impl std::io::Write) -> Result<()> {
+        let (width, height) = (bitmap.size.width, bitmap.size.height);
+        for (name, layer) in &self.layers {
+            if layer.size.width != width || layer.size.height != height {
+                bail!("layer {name} doesn't match the size of the image");
+            }
+        }
+
+        // channels must be stored in alphabetical order
+        // (name, layer, index of the component)
+        let mut channels: Vec<(String, &Bitmap, usize)> = vec![];
+        for (prefix, layer) in std::iter::once(("", bitmap))
+            .chain(self.layers.iter().map(|(name, layer)| (*name, *layer)))
+        {
+            let separator = if prefix.is_empty() { "" } else { "." };
+            for (component, channel) in ["R", "G", "B"].into_iter().enumerate() {
+                channels.push((format!("{prefix}{separator}{channel}"), layer, component));
+            }
+        }
+        channels.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let (type_id, type_size) = match self.pixel_type {
+            PixelType::Half => (1i32, 2),
+            PixelType::Float => (2i32, 4),
+        };
+
+        let mut file = vec![];
+        // magic number, then version 2 for a single-part scanline file
+        file.extend_from_slice(&20000630i32.to_le_bytes());
+        file.extend_from_slice(&2i32.to_le_bytes());
+
+        let mut chlist = vec![];
+        for (name, _, _) in &channels {
+            chlist.extend_from_slice(name.as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&type_id.to_le_bytes());
+            // pLinear and reserved bytes, then x and y sampling
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+
+        Self::write_attribute(&mut file, "channels", "chlist", &chlist);
+        Self::write_attribute(&mut file, "compression", "compression", &[0]);
+        Self::write_attribute(&mut file, "dataWindow", "box2i", &window);
+        Self::write_attribute(&mut file, "displayWindow", "box2i", &window);
+        Self::write_attribute(&mut file, "lineOrder", "lineOrder", &[0]);
+        Self::write_attribute(&mut file, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        Self::write_attribute(&mut file, "screenWindowCenter", "v2f", &[0; 8]);
+        Self::write_attribute(&mut file, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        file.push(0);
+
+        // offset table: one chunk per scanline, each chunk being the scanline's y coordinate,
+        // the size of its data and the data itself
+        let line_size = channels.len() * width as usize * type_size;
+        let table_end = file.len() + 8 * height as usize;
+        for line in 0..height as usize {
+            let offset = table_end + line * (8 + line_size);
+            file.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+
+        // scanlines go top to bottom
+        for line in 0..height {
+            file.extend_from_slice(&(line as i32).to_le_bytes());
+            file.extend_from_slice(&(line_size as i32).to_le_bytes());
+            let y = height - line - 1;
+            for &(_, layer, component) in &channels {
+                for x in 0..width {
+                    let v = layer.get(x, y).axis(component) as f32;
+                    match self.pixel_type {
+                        PixelType::Half => {
+                            file.extend_from_slice(&half::f16::from_f32(v).to_le_bytes())
+                        }
+                        PixelType::Float => file.extend_from_slice(&v.to_le_bytes()),
+                    }
+                }
+            }
+        }
+
+        target.write_all(&file)?;
+        Result::Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+
+// How a BVH node partitions its hittables between the two children
+#[derive(Clone, Copy, Debug, Default)]
+pub enum SplitMethod {
+    // split the longest axis of the centroid bounds in half
+    Midpoint,
+    // minimize the surface area heuristic over a set of candidate planes
+    #[default]
+    Sah,
+}
+
+// A node of a bounding volume hierarchy, itself a hittable
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+// Number of candidate split planes per axis considered by the SAH
+const SAH_BUCKETS: usize = 12;
+
+impl BvhNode {
+    // Builds a hierarchy over the hittables of the world. Unbounded hittables (which have no
+    // bounding box) can't be partitioned and are kept in a flat list next to the tree.
+    pub fn from_world(world: World, split: SplitMethod) -> Arc<dyn Hittable> {
+        let mut bounded = vec![];
+        let mut unbounded = vec![];
+        for hittable in world.hittables {
+            match hittable.bounding_box() {
+                Some(bbox) => bounded.push((bbox, hittable)),
+                None => unbounded.push(hittable),
+            }
+        }
+
+        let tree = (!bounded.is_empty()).then(|| Self::build(&mut bounded, split));
+        if unbounded.is_empty() {
+            if let Some(tree) = tree {
+                return tree;
+            }
+        }
+
+        let mut world = World::new();
+        world.hittables.extend(tree);
+        world.hittables.extend(unbounded);
+        Arc::new(world)
+    }
+
+    fn build(hittables: &mut [(Aabb, Arc<dyn Hittable>)], split: SplitMethod) -> Arc<dyn Hittable> {
+        if hittables.len() == 1 {
+            return Arc::clone(&hittables[0].1);
+        }
+
+        let bbox = hittables
+            .iter()
+            .map(|(bbox, _)| *bbox)
+            .reduce(Aabb::union)
+            .unwrap();
+        let centroid_bounds = hittables
+            .iter()
+            .map(|(bbox, _)| Aabb::new(bbox.centroid(), bbox.centroid()))
+            .reduce(Aabb::union)
+            .unwrap();
+        let axis = centroid_bounds.longest_axis();
+
+        let mid = match split {
+            SplitMethod::Midpoint => Self::partition_midpoint(hittables, centroid_bounds, axis),
+            SplitMethod::Sah => Self::partition_sah(hittables, centroid_bounds),
+        }
+        // fall back to an equal split if the plane failed to separate anything, e.g. when all
+        // the centroids coincide
+        .filter(|&mid| mid != 0 && mid != hittables.len())
+        .unwrap_or_else(|| {
+            let mid = hittables.len() / 2;
+            hittables.select_nth_unstable_by(mid, |(a, _), (b, _)| {
+                let (a, b) = (a.centroid().axis(axis), b.centroid().axis(axis));
+                a.partial_cmp(&b).unwrap()
+            });
+            mid
+        });
+
+        let (left, right) = hittables.split_at_mut(mid);
+        Arc::new(Self {
+            left: Self::build(left, split),
+            right: Self::build(right, split),
+            bbox,
+        })
+    }
+
+    fn partition_midpoint(
+        hittables: &mut [(Aabb, Arc<dyn Hittable>)],
+        centroid_bounds: Aabb,
+        axis: usize,
+    ) -> Option<usize> {
+        let plane = centroid_bounds.centroid().axis(axis);
+        Some(partition(hittables, |bbox| {
+            bbox.centroid().axis(axis) < plane
+        }))
+    }
+
+    fn partition_sah(
+        hittables: &mut [(Aabb, Arc<dyn Hittable>)],
+        centroid_bounds: Aabb,
+    ) -> Option<usize> {
+        let bucket_of = |axis: usize, bbox: &Aabb| {
+            let extent = centroid_bounds.extent().axis(axis);
+            let offset = bbox.centroid().axis(axis) - centroid_bounds.min.axis(axis);
+            ((SAH_BUCKETS as f64 * offset / extent) as usize).min(SAH_BUCKETS - 1)
+        };
+
+        // (cost, axis, index of the first bucket on the right side)
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            if centroid_bounds.extent().axis(axis) <= 0.0 {
+                continue;
+            }
+            let mut buckets: [(usize, Option<Aabb>); SAH_BUCKETS] = [(0, None); SAH_BUCKETS];
+            for (bbox, _) in hittables.iter() {
+                let bucket = &mut buckets[bucket_of(axis, bbox)];
+                bucket.0 += 1;
+                bucket.1 = Some(bucket.1.map_or(*bbox, |b| b.union(*bbox)));
+            }
+
+            for split in 1..SAH_BUCKETS {
+                let side_cost = |buckets: &[(usize, Option<Aabb>)]| {
+                    let count: usize = buckets.iter().map(|(count, _)| count).sum();
+                    let bbox = buckets
+                        .iter()
+                        .filter_map(|(_, bbox)| *bbox)
+                        .reduce(Aabb::union);
+                    count as f64 * bbox.map_or(0.0, Aabb::surface_area)
+                };
+                let cost = side_cost(&buckets[..split]) + side_cost(&buckets[split..]);
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        let (_, axis, split) = best?;
+        Some(partition(hittables, |bbox| bucket_of(axis, bbox) < split))
+    }
+}
+
+// Reorders the hittables so that those matching the predicate come first and returns their count
+fn partition(
+    hittables: &mut [(Aabb, Arc<dyn Hittable>)],
+    predicate: impl Fn(&Aabb) -> bool,
+) -> usize {
+    let mut mid = 0;
+    for i in 0..hittables.len() {
+        if predicate(&hittables[i].0) {
+            hittables.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl Hittable for BvhNode {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        let left = self.left.hit(ray, t_min, t_max, sampler);
+        let t_max = left.as_ref().map_or(t_max, |hit| hit.record.t);
+        let right = self.right.hit(ray, t_min, t_max, sampler);
+        right.or(left)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn shadow_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        let left = self.left.shadow_hit(ray, t_min, t_max, sampler);
+        let t_max = left.as_ref().map_or(t_max, |hit| hit.record.t);
+        let right = self.right.shadow_hit(ray, t_min, t_max, sampler);
+        right.or(left)
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return 1.0;
+        }
+        self.left.transmittance(ray, t_min, t_max, sampler)
+            * self.right.transmittance(ray, t_min, t_max, sampler)
+    }
+}
@@ -0,0 +1,331 @@
+// Checkpoints of progressive renders, from which a render that was stopped picks up where it left
+// off.
+//
+// A checkpoint holds what every pixel has gathered so far: the sum of its samples, the statistics
+// adaptive sampling goes by, and the index of its next sample. As the samplers work out each
+// sample from the seed, the pixel and the index alone, that index is all the state of the sampler
+// there is. Checkpoints also hold fingerprints of the scene and of the Config they were rendered
+// with, and resuming from them is refused if either has changed.
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::render::{Pixel, PixelStats};
+use crate::vec::*;
+
+// Where and how often a progressive render writes checkpoints
+#[derive(Clone, Debug)]
+pub struct Checkpointing {
+    pub path: PathBuf,
+    pub interval: Duration,
+    // a fingerprint of the scene, which the Config doesn't cover (see Scene::fingerprint)
+    pub scene: u64,
+    // picks up from the checkpoint at path if there is one, instead of starting over
+    pub resume: bool,
+}
+
+pub(crate) struct Checkpoint {
+    pub size: Size,
+    pub scene: u64,
+    pub config: u64,
+    pub target_samples: u32,
+    // the samples per pixel of the pass in progress
+    pub samples: u32,
+    pub pixels: Vec<Pixel>,
+}
+
+const MAGIC: &[u8; 8] = b"RAYCKPT1";
+
+impl Checkpoint {
+    // Writes to a temporary file next to path first, so that a render killed while writing still
+    // has its previous checkpoint.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let file = File::create(&temporary)
+            .with_context(|| format!("failed to create {}", temporary.display()))?;
+        let mut target = BufWriter::new(file);
+        target.write_all(MAGIC)?;
+        for value in [self.size.width, self.size.height] {
+            target.write_all(&value.to_le_bytes())?;
+        }
+        for value in [self.scene, self.config] {
+            target.write_all(&value.to_le_bytes())?;
+        }
+        for value in [self.target_samples, self.samples] {
+            target.write_all(&value.to_le_bytes())?;
+        }
+        for pixel in &self.pixels {
+            let sum = pixel.sum;
+            for value in [sum.x(), sum.y(), sum.z(), pixel.stats.mean, pixel.stats.m2] {
+                target.write_all(&value.to_le_bytes())?;
+            }
+            for value in [pixel.stats.count, pixel.next] {
+                target.write_all(&value.to_le_bytes())?;
+            }
+        }
+        target.into_inner()?.sync_all()?;
+
+        std::fs::rename(&temporary, path)
+            .with_context(|| format!("failed to replace {}", path.display()))
+    }
+
+    // fails unless saved is a checkpoint of the same render
+    fn check_resumable(&self, saved: &Checkpoint) -> Result<()> {
+        if saved.scene != self.scene {
+            bail!("the scene has changed since the checkpoint");
+        }
+        if saved.config != self.config {
+            bail!("the render settings have changed since the checkpoint");
+        }
+        let (width, height) = (saved.size.width, saved.size.height);
+        if (width, height) != (self.size.width, self.size.height) {
+            bail!("the checkpoint is of a {width}x{height} image");
+        }
+        if saved.target_samples != self.target_samples {
+            bail!(
+                "the checkpoint is of a render to {} samples per pixel",
+                saved.target_samples
+            );
+        }
+        Ok(())
+    }
+
+    // Loads the checkpoint at path, which must be of the same render as self.
+    pub fn load_resumable(&self, path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        self.read(&mut BufReader::new(file))
+            .with_context(|| format!("in checkpoint {}", path.display()))
+    }
+
+    fn read(&self, source: &mut impl Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        source.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("not a checkpoint");
+        }
+        let size = Size::new(read_u32(source)?, read_u32(source)?);
+        let scene = read_u64(source)?;
+        let config = read_u64(source)?;
+        let target_samples = read_u32(source)?;
+        let samples = read_u32(source)?;
+        let mut saved = Self {
+            size,
+            scene,
+            config,
+            target_samples,
+            samples,
+            pixels: vec![],
+        };
+        // before the pixels, so that a corrupt size can't make them overflow or run out of memory
+        self.check_resumable(&saved)?;
+
+        saved.pixels = (0..self.pixels.len())
+            .map(|_| {
+                let sum = Color::new(read_f64(source)?, read_f64(source)?, read_f64(source)?);
+                let (mean, m2) = (read_f64(source)?, read_f64(source)?);
+                let count = read_u32(source)?;
+                Ok(Pixel {
+                    sum,
+                    stats: PixelStats { count, mean, m2 },
+                    next: read_u32(source)?,
+                })
+            })
+            .collect::<Result<_>>()
+            .context("unexpected end of file")?;
+        Ok(saved)
+    }
+}
+
+// A fingerprint of several files, such as those a scene is loaded from, which is the same whichever
+// order they are added in. Each file is named by its path relative to a base directory, so that
+// moving them together keeps the fingerprint, but swapping two of them changes it.
+#[derive(Clone, Debug)]
+pub struct Fingerprint {
+    base_dir: PathBuf,
+    pieces: Vec<u64>,
+}
+
+impl Fingerprint {
+    pub fn new(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_owned(),
+            pieces: vec![],
+        }
+    }
+
+    // adds the contents of the file at path, which has already been read
+    pub fn add(&mut self, path: &Path, bytes: &[u8]) {
+        let name = path.strip_prefix(&self.base_dir).unwrap_or(path);
+        let name = name.to_string_lossy();
+        let hash = fnv(OFFSET_BASIS, &(name.len() as u64).to_le_bytes());
+        let hash = fnv(hash, name.as_bytes());
+        self.pieces.push(fnv(hash, bytes));
+    }
+
+    pub fn add_file(&mut self, path: &Path) -> Result<()> {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        self.add(path, &bytes);
+        Ok(())
+    }
+
+    pub fn value(&self) -> u64 {
+        let mut pieces = self.pieces.clone();
+        pieces.sort_unstable();
+        let bytes: Vec<u8> = pieces
+            .iter()
+            .flat_map(|piece| piece.to_le_bytes())
+            .collect();
+        fingerprint(&bytes)
+    }
+}
+
+const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+// A hash of bytes by FNV-1a, which unlike the hasher of the standard library is the same in every
+// build, so that checkpoints can be checked against it later
+pub fn fingerprint(bytes: &[u8]) -> u64 {
+    fnv(OFFSET_BASIS, bytes)
+}
+
+// continues the FNV-1a hash with more bytes
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn read_u32(source: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    source.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(source: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    source.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(source: &mut impl Read) -> Result<f64> {
+    Ok(f64::from_bits(read_u64(source)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        let pixels = (0..6)
+            .map(|i| Pixel {
+                sum: Color::new(i as f64, 0.5, -0.25 * i as f64),
+                stats: PixelStats {
+                    count: i,
+                    mean: 0.1 * i as f64,
+                    m2: 1.0 / 3.0,
+                },
+                next: 2 * i,
+            })
+            .collect();
+        Checkpoint {
+            size: Size::new(3, 2),
+            scene: 1,
+            config: 2,
+            target_samples: 16,
+            samples: 8,
+            pixels,
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ray-{}-{name}.ckpt", std::process::id()))
+    }
+
+    fn fields(pixel: &Pixel) -> ([f64; 5], [u32; 2]) {
+        let (sum, stats) = (pixel.sum, &pixel.stats);
+        (
+            [sum.x(), sum.y(), sum.z(), stats.mean, stats.m2],
+            [stats.count, pixel.next],
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = path("round-trip");
+        let saved = checkpoint();
+        saved.save(&path).unwrap();
+        let loaded = checkpoint().load_resumable(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.samples, saved.samples);
+        let fields = |checkpoint: &Checkpoint| checkpoint.pixels.iter().map(fields).collect();
+        let (loaded, saved): (Vec<_>, Vec<_>) = (fields(&loaded), fields(&saved));
+        assert_eq!(loaded, saved);
+    }
+
+    #[test]
+    fn refuses_a_different_render() {
+        let path = path("different");
+        checkpoint().save(&path).unwrap();
+        let refusal = |change: fn(&mut Checkpoint)| {
+            let mut expected = checkpoint();
+            change(&mut expected);
+            let error = expected.load_resumable(&path).err().unwrap();
+            format!("{:#}", error)
+        };
+
+        let scene = refusal(|c| c.scene = 3);
+        assert!(
+            scene.ends_with("the scene has changed since the checkpoint"),
+            "{scene}"
+        );
+        let config = refusal(|c| c.config = 3);
+        assert!(config.ends_with("the render settings have changed since the checkpoint"));
+        let size = refusal(|c| c.size = Size::new(2, 3));
+        assert!(size.ends_with("the checkpoint is of a 3x2 image"), "{size}");
+        let target = refusal(|c| c.target_samples = 32);
+        assert!(target.ends_with("the checkpoint is of a render to 16 samples per pixel"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated() {
+        let path = path("truncated");
+        checkpoint().save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 5]).unwrap();
+        let error = checkpoint().load_resumable(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", error).contains("unexpected end of file"));
+    }
+
+    #[test]
+    fn fingerprint_names_files() {
+        let fingerprint = |files: &[(&str, &[u8])]| {
+            let mut fingerprint = Fingerprint::new(Path::new("scenes"));
+            for (path, bytes) in files {
+                fingerprint.add(Path::new(path), bytes);
+            }
+            fingerprint.value()
+        };
+        let (a, b) = (
+            ("scenes/a.png", &b"red"[..]),
+            ("scenes/b.png", &b"blue"[..]),
+        );
+        assert_eq!(fingerprint(&[a, b]), fingerprint(&[b, a]));
+        let swapped = [("scenes/a.png", b.1), ("scenes/b.png", a.1)];
+        assert_ne!(fingerprint(&[a, b]), fingerprint(&swapped));
+        // moving the scene along with its files
+        let mut moved = Fingerprint::new(Path::new("elsewhere"));
+        moved.add(Path::new("elsewhere/a.png"), a.1);
+        moved.add(Path::new("elsewhere/b.png"), b.1);
+        assert_eq!(fingerprint(&[a, b]), moved.value());
+    }
+}
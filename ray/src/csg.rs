@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Operation {
+    // inside either solid
+    Union,
+    // inside both solids
+    Intersection,
+    // inside the left solid but not the right one
+    Difference,
+}
+
+// Constructive solid geometry: a boolean combination of two closed hittables (those reporting
+// spans). The surfaces of the result keep the materials of the solids they come from; surfaces of
+// the right solid carved out of the left one face the other way.
+pub struct Csg {
+    operation: Operation,
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Option<Aabb>,
+}
+
+impl Csg {
+    pub fn new(
+        operation: Operation,
+        left: Arc<dyn Hittable>,
+        right: Arc<dyn Hittable>,
+    ) -> Arc<Self> {
+        let (left_box, right_box) = (left.bounding_box(), right.bounding_box());
+        let bbox = match operation {
+            Operation::Union => left_box.zip(right_box).map(|(a, b)| a.union(b)),
+            // overlapping boxes, which bound both solids
+            Operation::Intersection => match (left_box, right_box) {
+                (Some(a), Some(b)) => Some(Aabb {
+                    min: a.min.max(b.min),
+                    max: a.max.min(b.max),
+                }),
+                (a, b) => a.or(b),
+            },
+            Operation::Difference => left_box,
+        };
+        Arc::new(Self {
+            operation,
+            left,
+            right,
+            bbox,
+        })
+    }
+
+    fn contains(&self, in_left: bool, in_right: bool) -> bool {
+        match self.operation {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        if let Some(bbox) = self.bbox {
+            if !bbox.hit(ray, t_min, t_max) {
+                return None;
+            }
+        }
+        self.spans(ray)?
+            .into_iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|hit| t_min <= hit.record.t && hit.record.t <= t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    // Sweeps along the ray through the boundaries of both solids' spans, starting a span of the
+    // result wherever the combination turns from outside to inside and ending it where it turns
+    // back.
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let mut boundaries: Vec<(bool, bool, HitResult)> = vec![];
+        for (is_left, spans) in [
+            (true, self.left.spans(ray)?),
+            (false, self.right.spans(ray)?),
+        ] {
+            for span in spans {
+                boundaries.push((is_left, true, span.enter));
+                boundaries.push((is_left, false, span.exit));
+            }
+        }
+        boundaries.sort_by(|a, b| a.2.record.t.total_cmp(&b.2.record.t));
+
+        let flip_right = matches!(self.operation, Operation::Difference);
+        let (mut in_left, mut in_right) = (false, false);
+        let mut enter = None;
+        let mut spans = vec![];
+        for (is_left, entering, mut hit) in boundaries {
+            let was_inside = self.contains(in_left, in_right);
+            if is_left {
+                in_left = entering;
+            } else {
+                in_right = entering;
+            }
+            let inside = self.contains(in_left, in_right);
+            if inside == was_inside {
+                continue;
+            }
+
+            if !is_left && flip_right {
+                // the outward normal of the carved out surface points into the right solid
+                hit.record.front_face = !hit.record.front_face;
+            }
+            match enter.take() {
+                None => enter = Some(hit),
+                Some(enter) => spans.push(Span { enter, exit: hit }),
+            }
+        }
+        Some(spans)
+    }
+}
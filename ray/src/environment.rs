@@ -0,0 +1,447 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::bitmap::Bitmap;
+use crate::light::LightSample;
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// The light arriving from infinitely far away, seen by rays escaping the scene
+pub trait Environment: Send + Sync {
+    // radiance arriving from direction (which need not be a unit vector)
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    // Whether the environment can be importance sampled, in which case the renderer samples it
+    // like the other lights.
+    fn can_sample(&self) -> bool {
+        false
+    }
+
+    // picks a direction towards the environment
+    fn sample(&self, _: &mut dyn Sampler) -> Option<LightSample> {
+        None
+    }
+
+    // the solid angle density with which sample() picks direction
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+pub struct ConstantEnvironment {
+    color: Color,
+}
+
+// A vertical blend between two colors, from straight down to straight up
+pub struct Gradient {
+    bottom: Color,
+    top: Color,
+}
+
+// An equirectangular (latitude-longitude) image of the surroundings. The centre of the image is
+// in the -z direction and its top row is straight up; rotation turns the map counterclockwise
+// about the y axis, as seen from above.
+pub struct EnvironmentMap {
+    image: Bitmap,
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+// A clear daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model for
+// Daylight", with the sun as a small disk dimmed by the atmosphere. Below the horizon is a flat
+// ground reflecting the sun and sky light by its albedo. Radiance is scaled so that a white
+// diffuse surface facing a high sun is about 1.
+pub struct Sky {
+    // unit direction towards the sun
+    sun: Vec3,
+    // the zenith luminance and chromaticity (Y, x, y), and the distribution of each over the sky
+    zenith: [f64; 3],
+    coefficients: [[f64; 5]; 3],
+    // the zenith angle of the sun, as the sky model sees it
+    sun_theta: f64,
+    sun_radiance: Color,
+    ground_radiance: Color,
+}
+
+// A piecewise-constant distribution over [0, 1]
+struct Distribution1D {
+    // cdf[i] is the probability of landing below i / n, with n + 1 entries ending in 1
+    cdf: Vec<f64>,
+    // the sum of the weights, over n
+    integral: f64,
+}
+
+// A piecewise-constant distribution over [0, 1]^2, sampled by picking a row and then a column
+struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl ConstantEnvironment {
+    pub fn new(color: Color) -> Arc<Self> {
+        Arc::new(Self { color })
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn radiance(&self, _: Vec3) -> Color {
+        self.color
+    }
+}
+
+impl Gradient {
+    pub fn new(bottom: Color, top: Color) -> Arc<Self> {
+        Arc::new(Self { bottom, top })
+    }
+
+    // the white to blue sky
+    pub fn sky() -> Arc<Self> {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let t = 0.5 * (direction.as_unit().y() + 1.0);
+        self.bottom.lerp(t, self.top)
+    }
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Bitmap, rotation: f64, intensity: f64) -> Arc<Self> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        assert!(width > 0 && height > 0);
+        // weigh pixels by how much solid angle they cover, which shrinks towards the poles
+        let weights: Vec<Vec<f64>> = (0..height)
+            .map(|row| {
+                let sine = (PI * (row as f64 + 0.5) / height as f64).sin();
+                (0..width)
+                    .map(|column| {
+                        let (x, y) = Self::pixel(&image, column, row);
+                        luminance(image.get(x, y)) * sine
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Arc::new(Self {
+            image,
+            rotation,
+            intensity,
+            distribution: Distribution2D::new(weights),
+        })
+    }
+
+    // the bitmap coordinates of a pixel counted from the top left of the image
+    fn pixel(image: &Bitmap, column: usize, row: usize) -> (u32, u32) {
+        (column as u32, image.height() - row as u32 - 1)
+    }
+
+    // map coordinates in [0, 1]^2 (from the top left) of direction
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.as_unit();
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let phi = d.x().atan2(-d.z()) - self.rotation;
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let theta = PI * v;
+        let phi = 2.0 * PI * (u - 0.5) + self.rotation;
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let (width, height) = (self.image.width() as usize, self.image.height() as usize);
+        let column = ((u * width as f64) as usize).min(width - 1);
+        let row = ((v * height as f64) as usize).min(height - 1);
+        let (x, y) = Self::pixel(&self.image, column, row);
+        self.intensity * self.image.get(x, y)
+    }
+
+    fn can_sample(&self) -> bool {
+        true
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let (xi_u, xi_v) = sampler.get_2d();
+        let (u, v, map_pdf) = self.distribution.sample(xi_u, xi_v)?;
+        let sine = (PI * v).sin();
+        if sine <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: self.uv_to_direction(u, v),
+            // the map spans 2 pi by pi radians, each shrunk by the sine of the polar angle
+            pdf: map_pdf / (2.0 * PI * PI * sine),
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sine = (PI * v).sin();
+        if sine <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sine)
+    }
+}
+
+impl Sky {
+    // angular radius of the sun
+    const SUN_RADIUS: f64 = 0.00465;
+    // luminance of the sun outside the atmosphere, in cd/m^2
+    const SUN_LUMINANCE: f64 = 1.88e9;
+    // the luminance (in cd/m^2) that maps to a radiance of 1
+    const UNIT_LUMINANCE: f64 = 40000.0;
+
+    // turbidity describes the haziness of the air, from 2 for a very clear sky to 10 for haze
+    pub fn new(sun_direction: Vec3, turbidity: f64, ground_albedo: Color) -> Arc<Self> {
+        assert!((2.0..=10.0).contains(&turbidity));
+        assert!(!sun_direction.is_near_zero());
+        let sun = sun_direction.as_unit();
+        // the model breaks down with the sun below the horizon
+        let theta = sun.y().clamp(-1.0, 1.0).acos().min(0.49 * PI);
+        let t = turbidity;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let theta = [theta.powi(3), theta.powi(2), theta, 1.0];
+            let row = |r: [f64; 4]| r.iter().zip(theta).map(|(a, b)| a * b).sum::<f64>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let coefficients = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let mut sky = Self {
+            sun,
+            // the model gives luminance in kcd/m^2
+            zenith: [1000.0 * zenith_luminance, zenith_x, zenith_y],
+            coefficients,
+            sun_theta: theta,
+            sun_radiance: Self::sun_radiance(sun, turbidity),
+            ground_radiance: Color::ZERO,
+        };
+
+        // irradiance on the ground from the sun and (numerically integrated) the sky
+        let mut irradiance =
+            sky.sun_radiance * (2.0 * PI * (1.0 - Self::SUN_RADIUS.cos())) * sun.y().max(0.0);
+        let (rings, segments) = (32, 64);
+        for i in 0..rings {
+            let theta = 0.5 * PI * (i as f64 + 0.5) / rings as f64;
+            for j in 0..segments {
+                let phi = 2.0 * PI * (j as f64 + 0.5) / segments as f64;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let solid_angle =
+                    theta.sin() * (0.5 * PI / rings as f64) * (2.0 * PI / segments as f64);
+                irradiance += sky.sky_radiance(direction) * theta.cos() * solid_angle;
+            }
+        }
+        sky.ground_radiance = ground_albedo * irradiance / PI;
+        Arc::new(sky)
+    }
+
+    // radiance of the sun disk through the atmosphere, from the optical depths of Rayleigh and
+    // aerosol scattering at the red, green and blue wavelengths (680, 550 and 440 nm)
+    fn sun_radiance(sun: Vec3, turbidity: f64) -> Color {
+        if sun.y() <= 0.0 {
+            return Color::ZERO;
+        }
+        // Kasten and Young's relative air mass
+        let zenith_degrees = sun.y().acos().to_degrees();
+        let air_mass = 1.0 / (sun.y() + 0.50572 * (96.07995 - zenith_degrees).powf(-1.6364));
+        // Angstrom's turbidity coefficient and wavelength exponent
+        let beta = 0.04608 * turbidity - 0.04586;
+        let transmittance = |wavelength: f64| {
+            let rayleigh = 0.008735 * wavelength.powf(-4.08);
+            let aerosol = beta * wavelength.powf(-1.3);
+            (-air_mass * (rayleigh + aerosol)).exp()
+        };
+        Self::SUN_LUMINANCE / Self::UNIT_LUMINANCE
+            * Color::new(
+                transmittance(0.68),
+                transmittance(0.55),
+                transmittance(0.44),
+            )
+    }
+
+    // Perez et al.'s distribution of a sky quantity
+    fn perez([a, b, c, d, e]: [f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    // radiance of the sky (without the sun) from a unit direction above the horizon
+    fn sky_radiance(&self, direction: Vec3) -> Color {
+        let cos_theta = direction.y().max(1e-3);
+        let gamma = direction.dot(self.sun).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] = [0, 1, 2].map(|i| {
+            self.zenith[i] * Self::perez(self.coefficients[i], cos_theta, gamma)
+                / Self::perez(self.coefficients[i], 1.0, self.sun_theta)
+        });
+
+        // Yxy to XYZ to linear Rec. 709
+        let big_x = x / y * luminance;
+        let big_z = (1.0 - x - y) / y * luminance;
+        let rgb = Color::new(
+            3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+            -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+            0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+        );
+        Color::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0)) / Self::UNIT_LUMINANCE
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let direction = direction.as_unit();
+        if direction.y() < 0.0 {
+            return self.ground_radiance;
+        }
+        let mut radiance = self.sky_radiance(direction);
+        if direction.dot(self.sun) >= Self::SUN_RADIUS.cos() {
+            radiance += self.sun_radiance;
+        }
+        radiance
+    }
+
+    // The sun is sampled, as it is far too small for scattered rays to find. The rest of the sky
+    // is smooth enough to be left to them.
+    fn can_sample(&self) -> bool {
+        self.sun.y() > 0.0
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let cos_max = Self::SUN_RADIUS.cos();
+        let (xi, xi_phi) = sampler.get_2d();
+        let cosine = 1.0 - xi * (1.0 - cos_max);
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let phi = 2.0 * PI * xi_phi;
+
+        let (s, t) = self.sun.basis();
+        Some(LightSample {
+            direction: cosine * self.sun + sine * (phi.cos() * s + phi.sin() * t),
+            pdf: 1.0 / (2.0 * PI * (1.0 - cos_max)),
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let cos_max = Self::SUN_RADIUS.cos();
+        if direction.as_unit().dot(self.sun) < cos_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+}
+
+// relative luminance of a linear Rec. 709 color
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+impl Distribution1D {
+    fn new(weights: &[f64]) -> Self {
+        let n = weights.len() as f64;
+        let mut cdf = Vec::with_capacity(weights.len() + 1);
+        cdf.push(0.0);
+        for weight in weights {
+            cdf.push(cdf.last().unwrap() + weight.max(0.0) / n);
+        }
+        let integral = *cdf.last().unwrap();
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        }
+        Self { cdf, integral }
+    }
+
+    fn len(&self) -> usize {
+        self.cdf.len() - 1
+    }
+
+    // the probability of each segment, over the segment width
+    fn segment_pdf(&self, index: usize) -> f64 {
+        (self.cdf[index + 1] - self.cdf[index]) * self.len() as f64
+    }
+
+    // a point in [0, 1] distributed according to the weights, with its segment and density
+    fn sample(&self, xi: f64) -> Option<(f64, usize, f64)> {
+        if self.integral <= 0.0 {
+            return None;
+        }
+        // the last segment whose cdf is <= xi
+        let index = (self.cdf.partition_point(|&c| c <= xi) - 1).min(self.len() - 1);
+        let pdf = self.segment_pdf(index);
+        let offset = (xi - self.cdf[index]) / (self.cdf[index + 1] - self.cdf[index]);
+        Some(((index as f64 + offset) / self.len() as f64, index, pdf))
+    }
+}
+
+impl Distribution2D {
+    // weights are given row by row
+    fn new(weights: Vec<Vec<f64>>) -> Self {
+        let rows: Vec<_> = weights.iter().map(|row| Distribution1D::new(row)).collect();
+        let row_integrals: Vec<_> = rows.iter().map(|row| row.integral).collect();
+        Self {
+            marginal: Distribution1D::new(&row_integrals),
+            rows,
+        }
+    }
+
+    // a point (u, v) with v picking the row, and its density
+    fn sample(&self, xi_u: f64, xi_v: f64) -> Option<(f64, f64, f64)> {
+        let (v, row, row_pdf) = self.marginal.sample(xi_v)?;
+        let (u, _, column_pdf) = self.rows[row].sample(xi_u)?;
+        Some((u, v, row_pdf * column_pdf))
+    }
+
+    fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        let columns = self.rows[row].len();
+        let column = ((u * columns as f64) as usize).min(columns - 1);
+        self.marginal.segment_pdf(row) * self.rows[row].segment_pdf(column)
+    }
+}
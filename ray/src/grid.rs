@@ -0,0 +1,276 @@
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::aabb::Aabb;
+use crate::vec::*;
+
+// A scalar field (such as the density or temperature of a simulation) sampled on a regular grid of
+// voxels spanning a box. Voxels outside the grid read as 0.
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    bounds: Aabb,
+    storage: Storage,
+    // the largest voxel value
+    max: f64,
+}
+
+enum Storage {
+    // every voxel, x varying fastest and z slowest
+    Dense(Vec<f32>),
+    // Only the leaves (blocks of LEAF_SIZE^3 voxels) holding non-zero values, as in OpenVDB. index
+    // maps each leaf of the coarse grid of leaves to its values, or to EMPTY_LEAF.
+    Sparse {
+        leaves: Vec<[f32; LEAF_VOXELS]>,
+        index: Vec<u32>,
+        leaf_resolution: [usize; 3],
+    },
+}
+
+// number of voxels along each side of a leaf
+pub const LEAF_SIZE: usize = 8;
+pub const LEAF_VOXELS: usize = LEAF_SIZE * LEAF_SIZE * LEAF_SIZE;
+const EMPTY_LEAF: u32 = u32::MAX;
+// the most voxels a loaded grid may have, past which its header is taken to be corrupt
+const MAX_VOXELS: usize = u32::MAX as usize;
+
+impl VoxelGrid {
+    // a grid storing every voxel, x varying fastest and z slowest
+    pub fn dense(resolution: [usize; 3], bounds: Aabb, values: Vec<f32>) -> Self {
+        assert_eq!(values.len(), resolution.iter().product::<usize>());
+        let max = values.iter().fold(0.0f32, |max, &value| max.max(value));
+        Self {
+            resolution,
+            bounds,
+            storage: Storage::Dense(values),
+            max: max as f64,
+        }
+    }
+
+    // A grid storing only some leaves, given by the coordinates of their first voxel (multiples of
+    // LEAF_SIZE) and their values, x varying fastest and z slowest.
+    pub fn sparse(
+        resolution: [usize; 3],
+        bounds: Aabb,
+        leaves: Vec<([usize; 3], [f32; LEAF_VOXELS])>,
+    ) -> Self {
+        let leaf_resolution = resolution.map(|n| n.div_ceil(LEAF_SIZE));
+        let mut index = vec![EMPTY_LEAF; leaf_resolution.iter().product()];
+        let mut max = 0.0f32;
+        let mut values = Vec::with_capacity(leaves.len());
+        for ([x, y, z], leaf) in leaves {
+            assert!(x % LEAF_SIZE == 0 && y % LEAF_SIZE == 0 && z % LEAF_SIZE == 0);
+            let [i, j, k] = [x, y, z].map(|c| c / LEAF_SIZE);
+            assert!(i < leaf_resolution[0] && j < leaf_resolution[1] && k < leaf_resolution[2]);
+            index[(k * leaf_resolution[1] + j) * leaf_resolution[0] + i] = values.len() as u32;
+            max = leaf.iter().fold(max, |max, &value| max.max(value));
+            values.push(leaf);
+        }
+        Self {
+            resolution,
+            bounds,
+            storage: Storage::Sparse {
+                leaves: values,
+                index,
+                leaf_resolution,
+            },
+            max: max as f64,
+        }
+    }
+
+    // Loads a dense grid in Mitsuba's .vol format (single channel, of 32 bit floats or bytes), or a
+    // sparse grid in the .svol format:
+    //
+    //     "SVOL", version (u32, 1), resolution (3 u32), bounds (min and max corners, 6 f32),
+    //     number of leaves (u32), then for each leaf the coordinates of its first voxel (3 u32)
+    //     followed by its 8^3 values (f32, x varying fastest)
+    //
+    // all little-endian.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let reader = BufReader::new(file);
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let grid = match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("vol") => Self::decode_vol(reader),
+            Some("svol") => Self::decode_svol(reader),
+            _ => bail!("unsupported grid format"),
+        };
+        grid.with_context(|| format!("failed to load {}", path.display()))
+    }
+
+    fn decode_vol(mut reader: impl Read) -> Result<Self> {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        if header != *b"VOL\x03" {
+            bail!("not a version 3 .vol file");
+        }
+        let encoding = read_u32(&mut reader)?;
+        let resolution = read_resolution(&mut reader)?;
+        if read_u32(&mut reader)? != 1 {
+            bail!("only single channel grids are supported");
+        }
+        let bounds = read_bounds(&mut reader)?;
+
+        let count: usize = resolution.iter().product();
+        let values = match encoding {
+            1 => read_bytes(&mut reader, 4 * count)?
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect(),
+            3 => read_bytes(&mut reader, count)?
+                .into_iter()
+                .map(|b| b as f32 / 255.0)
+                .collect(),
+            _ => bail!("unsupported encoding {encoding}, expected 32 bit floats or bytes"),
+        };
+        Ok(Self::dense(resolution, bounds, values))
+    }
+
+    fn decode_svol(mut reader: impl Read) -> Result<Self> {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        if header != *b"SVOL" || read_u32(&mut reader)? != 1 {
+            bail!("not a version 1 .svol file");
+        }
+        let resolution = read_resolution(&mut reader)?;
+        let bounds = read_bounds(&mut reader)?;
+
+        let leaf_resolution = resolution.map(|n| n.div_ceil(LEAF_SIZE));
+        let count = read_u32(&mut reader)?;
+        if count as usize > leaf_resolution.iter().product() {
+            bail!("more leaves ({count}) than the grid has room for");
+        }
+        let mut leaves = vec![];
+        for _ in 0..count {
+            let mut origin = [0; 3];
+            for c in &mut origin {
+                *c = read_u32(&mut reader)? as usize;
+            }
+            let inside = (0..3).all(|axis| {
+                origin[axis] % LEAF_SIZE == 0 && origin[axis] / LEAF_SIZE < leaf_resolution[axis]
+            });
+            if !inside {
+                bail!("leaf at {origin:?} isn't aligned to the leaves of the grid");
+            }
+            let mut values = [0.0; LEAF_VOXELS];
+            for value in &mut values {
+                *value = read_f32(&mut reader)?;
+            }
+            leaves.push((origin, values));
+        }
+        Ok(Self::sparse(resolution, bounds, leaves))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    // the value of a voxel, 0 outside of the grid
+    fn voxel(&self, x: i64, y: i64, z: i64) -> f64 {
+        let [nx, ny, nz] = self.resolution.map(|n| n as i64);
+        if x < 0 || y < 0 || z < 0 || x >= nx || y >= ny || z >= nz {
+            return 0.0;
+        }
+        let (x, y, z) = (x as usize, y as usize, z as usize);
+        let value = match &self.storage {
+            Storage::Dense(values) => values[(z * ny as usize + y) * nx as usize + x],
+            Storage::Sparse {
+                leaves,
+                index,
+                leaf_resolution,
+            } => {
+                let [i, j, k] = [x, y, z].map(|c| c / LEAF_SIZE);
+                let leaf = index[(k * leaf_resolution[1] + j) * leaf_resolution[0] + i];
+                if leaf == EMPTY_LEAF {
+                    return 0.0;
+                }
+                let [x, y, z] = [x, y, z].map(|c| c % LEAF_SIZE);
+                leaves[leaf as usize][(z * LEAF_SIZE + y) * LEAF_SIZE + x]
+            }
+        };
+        value as f64
+    }
+
+    // the trilinearly interpolated value at a point, with voxel centres at half-integer
+    // coordinates of the grid
+    pub fn lookup(&self, point: Point3) -> f64 {
+        let extent = self.bounds.extent();
+        let grid = |axis: usize| {
+            (point.axis(axis) - self.bounds.min.axis(axis)) / extent.axis(axis)
+                * self.resolution[axis] as f64
+                - 0.5
+        };
+        let (x, y, z) = (grid(0), grid(1), grid(2));
+        let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+        let (tx, ty, tz) = (x - x0, y - y0, z - z0);
+        let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+
+        let lerp = |t: f64, a: f64, b: f64| a + t * (b - a);
+        let along_x = |y, z| lerp(tx, self.voxel(x0, y, z), self.voxel(x0 + 1, y, z));
+        let along_y = |z| lerp(ty, along_x(y0, z), along_x(y0 + 1, z));
+        lerp(tz, along_y(z0), along_y(z0 + 1))
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_resolution(reader: &mut impl Read) -> Result<[usize; 3]> {
+    let mut resolution = [0; 3];
+    for n in &mut resolution {
+        *n = read_u32(reader)? as usize;
+    }
+    if resolution.contains(&0) {
+        bail!("empty grid");
+    }
+    let count = resolution
+        .iter()
+        .try_fold(1usize, |count, &n| count.checked_mul(n))
+        .filter(|&count| count <= MAX_VOXELS);
+    if count.is_none() {
+        let [x, y, z] = resolution;
+        bail!("grid of {x}x{y}x{z} voxels is too large");
+    }
+    Ok(resolution)
+}
+
+// Reads len bytes, allocating as they arrive rather than up front, so that a corrupt header can't
+// make it allocate more than the file holds.
+fn read_bytes(reader: &mut impl Read, len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        bail!("unexpected end of file");
+    }
+    Ok(bytes)
+}
+
+fn read_bounds(reader: &mut impl Read) -> Result<Aabb> {
+    let mut corners = [0.0; 6];
+    for c in &mut corners {
+        *c = read_f32(reader)? as f64;
+    }
+    let [x0, y0, z0, x1, y1, z1] = corners;
+    let bounds = Aabb::new(Point3::new(x0, y0, z0), Point3::new(x1, y1, z1));
+    let extent = bounds.extent();
+    if extent.x() <= 0.0 || extent.y() <= 0.0 || extent.z() <= 0.0 {
+        bail!("empty grid bounds");
+    }
+    Ok(bounds)
+}
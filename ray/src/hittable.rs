@@ -1,16 +1,67 @@
-use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::aabb::Aabb;
+use crate::light::{Light, LightSample};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec::*;
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitResult>;
+pub trait Hittable: Send + Sync {
+    // The nearest hit of the ray within (t_min, t_max). The sampler provides the random numbers of
+    // hittables that are hit at random distances, such as participating media.
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult>;
+
+    // the box enclosing the hittable, or None if it is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // The intervals of the ray's whole line (negative t included) that lie inside the hittable,
+    // in order, for closed hittables enclosing a volume. None for those that don't.
+    fn spans(&self, _ray: &Ray) -> Option<Vec<Span>> {
+        None
+    }
+
+    // The nearest hit of a shadow ray, which passes through participating media instead of
+    // scattering in them, the light they let through being given by transmittance. The same as
+    // hit for everything else.
+    fn shadow_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        self.hit(ray, t_min, t_max, sampler)
+    }
+
+    // the fraction of light passing through the participating media of the hittable along the ray
+    // within (t_min, t_max), which may be a random estimate. 1 for surfaces.
+    fn transmittance(
+        &self,
+        _ray: &Ray,
+        _t_min: f64,
+        _t_max: f64,
+        _sampler: &mut dyn Sampler,
+    ) -> f64 {
+        1.0
+    }
+}
+
+// An interval of a ray inside a solid, between the hits where the ray enters and exits it
+pub struct Span {
+    pub enter: HitResult,
+    pub exit: HitResult,
 }
 
 pub struct HitResult {
     pub record: HitRecord,
-    pub material: Rc<dyn Material>,
+    pub material: Arc<dyn Material>,
 }
 
 #[derive(Clone)]
@@ -21,17 +72,31 @@ pub struct HitRecord {
     pub normal: Vec3,
     // whether the ray hit the front face
     pub front_face: bool,
+    // surface coordinates, for texturing
+    pub u: f64,
+    pub v: f64,
 }
 
+#[derive(Default)]
 pub struct World {
-    pub hittables: Vec<Rc<dyn Hittable>>,
+    pub hittables: Vec<Arc<dyn Hittable>>,
 }
 
 #[derive(Clone)]
 pub struct Sphere {
     pub centre: Point3,
     pub radius: f64,
-    pub material: Rc<dyn Material>,
+    pub material: Arc<dyn Material>,
+}
+
+// A sphere moving at a constant speed from one centre to another over an interval of time, staying
+// put before and after it
+pub struct MovingSphere {
+    // the sphere at the start of the motion
+    sphere: Sphere,
+    end_centre: Point3,
+    start_time: f64,
+    end_time: f64,
 }
 
 impl World {
@@ -39,26 +104,60 @@ impl World {
         World { hittables: vec![] }
     }
 
-    pub fn add(&mut self, hittable: Rc<dyn Hittable>) {
+    pub fn add(&mut self, hittable: Arc<dyn Hittable>) {
         self.hittables.push(hittable)
     }
 }
 
 impl Hittable for World {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
         let nearest_hit = self
             .hittables
             .iter()
-            .filter_map(|hittable| hittable.hit(ray, t_min, t_max))
+            .filter_map(|hittable| hittable.hit(ray, t_min, t_max, sampler))
             .min_by(|a, b| a.record.t.partial_cmp(&b.record.t).unwrap());
 
         nearest_hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.hittables
+            .iter()
+            .map(|hittable| hittable.bounding_box())
+            .reduce(|a, b| Some(a?.union(b?)))
+            .flatten()
+    }
+
+    fn shadow_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        self.hittables
+            .iter()
+            .filter_map(|hittable| hittable.shadow_hit(ray, t_min, t_max, sampler))
+            .min_by(|a, b| a.record.t.partial_cmp(&b.record.t).unwrap())
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        self.hittables
+            .iter()
+            .map(|hittable| hittable.transmittance(ray, t_min, t_max, sampler))
+            .product()
+    }
 }
 
 impl Sphere {
-    pub fn new(centre: Point3, radius: f64, material: Rc<dyn Material>) -> Rc<Self> {
-        Rc::new(Self {
+    pub fn new(centre: Point3, radius: f64, material: Arc<dyn Material>) -> Arc<Self> {
+        Arc::new(Self {
             centre,
             radius,
             material,
@@ -67,8 +166,23 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
-        let oc = ray.origin - self.centre;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        self.hit_around(self.centre, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox_around(self.centre))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        self.spans_around(self.centre, ray)
+    }
+}
+
+impl Sphere {
+    // The sphere's geometry, with the centre moved to the given one (for MovingSphere)
+    fn hit_around(&self, centre: Point3, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let oc = ray.origin - centre;
         let a = ray.direction.length_sq();
         let half_b = oc.dot(ray.direction);
         let c = oc.length_sq() - self.radius * self.radius;
@@ -87,22 +201,162 @@ impl Hittable for Sphere {
                 return None;
             }
         }
+        self.hit_at(centre, ray, root)
+    }
+
+    fn bbox_around(&self, centre: Point3) -> Aabb {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(centre - radius, centre + radius)
+    }
+
+    fn spans_around(&self, centre: Point3, ray: &Ray) -> Option<Vec<Span>> {
+        let oc = ray.origin - centre;
+        let a = ray.direction.length_sq();
+        let half_b = oc.dot(ray.direction);
+        let c = oc.length_sq() - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant <= 0.0 {
+            return Some(vec![]);
+        }
+        let sqrtd = discriminant.sqrt();
+        let span = Span {
+            enter: self.hit_at(centre, ray, (-half_b - sqrtd) / a)?,
+            exit: self.hit_at(centre, ray, (-half_b + sqrtd) / a)?,
+        };
+        Some(vec![span])
+    }
 
-        let hit_point = ray.at(root);
-        let out_normal = (hit_point - self.centre) / self.radius;
+    fn hit_at(&self, centre: Point3, ray: &Ray, t: f64) -> Option<HitResult> {
+        let hit_point = ray.at(t);
+        let out_normal = (hit_point - centre) / self.radius;
+        let (u, v) = Self::uv(out_normal);
         HitResult::new(
-            HitRecord::from_outward_normal(root, hit_point, ray.direction, out_normal),
-            Rc::clone(&self.material),
+            HitRecord::from_outward_normal(t, hit_point, ray.direction, out_normal).with_uv(u, v),
+            Arc::clone(&self.material),
         )
     }
+
+    // Maps a point of the unit sphere to (u, v): u is the angle around the y axis starting from
+    // -x, and v the angle from -y to +y, both scaled to [0, 1].
+    fn uv(point: Point3) -> (f64, f64) {
+        let theta = (-point.y()).acos();
+        let phi = (-point.z()).atan2(point.x()) + std::f64::consts::PI;
+        (
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        )
+    }
+
+    // cosine of the half-angle of the cone the sphere covers as seen from origin, None if origin
+    // is inside the sphere
+    fn cone_cosine(&self, origin: Point3) -> Option<f64> {
+        let distance_sq = (self.centre - origin).length_sq();
+        let radius_sq = self.radius * self.radius;
+        (distance_sq > radius_sq).then(|| (1.0 - radius_sq / distance_sq).sqrt())
+    }
+}
+
+impl Light for Sphere {
+    // uniformly samples the cone of directions that the sphere covers
+    fn sample(&self, origin: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let cos_max = self.cone_cosine(origin)?;
+        let (xi, xi_phi) = sampler.get_2d();
+        let cosine = 1.0 - xi * (1.0 - cos_max);
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * xi_phi;
+
+        let axis = (self.centre - origin).as_unit();
+        let (s, t) = axis.basis();
+        let direction = cosine * axis + sine * (phi.cos() * s + phi.sin() * t);
+        Some(LightSample {
+            direction,
+            pdf: 1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_max)),
+        })
+    }
+
+    fn pdf(&self, origin: Point3, direction: Vec3) -> f64 {
+        let Some(cos_max) = self.cone_cosine(origin) else {
+            return 0.0;
+        };
+        let axis = (self.centre - origin).as_unit();
+        if direction.as_unit().dot(axis) < cos_max {
+            return 0.0;
+        }
+        1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_max))
+    }
+}
+
+impl MovingSphere {
+    pub fn new(
+        start_centre: Point3,
+        end_centre: Point3,
+        start_time: f64,
+        end_time: f64,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            sphere: Sphere {
+                centre: start_centre,
+                radius,
+                material,
+            },
+            end_centre,
+            start_time,
+            end_time,
+        })
+    }
+
+    fn centre(&self, time: f64) -> Point3 {
+        // a sphere that starts and ends at the same time jumps from one centre to the other
+        if self.end_time <= self.start_time {
+            return if time < self.start_time {
+                self.sphere.centre
+            } else {
+                self.end_centre
+            };
+        }
+        let fraction =
+            ((time - self.start_time) / (self.end_time - self.start_time)).clamp(0.0, 1.0);
+        self.sphere.centre.lerp(fraction, self.end_centre)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        self.sphere
+            .hit_around(self.centre(ray.time), ray, t_min, t_max)
+    }
+
+    // encloses the sphere along the whole motion
+    fn bounding_box(&self) -> Option<Aabb> {
+        let start = self.sphere.bbox_around(self.sphere.centre);
+        Some(start.union(self.sphere.bbox_around(self.end_centre)))
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        self.sphere.spans_around(self.centre(ray.time), ray)
+    }
 }
 
 impl HitResult {
-    pub fn new(record: HitRecord, material: Rc<dyn Material>) -> Option<Self> {
+    pub fn new(record: HitRecord, material: Arc<dyn Material>) -> Option<Self> {
         Self { record, material }.into()
     }
 }
 
+impl Span {
+    // The span of a convex solid, from the first to the last of the hits where the ray crosses
+    // its surface. None if the ray misses or only grazes it.
+    pub fn convex(mut hits: Vec<HitResult>) -> Option<Self> {
+        hits.sort_by(|a, b| a.record.t.total_cmp(&b.record.t));
+        let exit = hits.pop()?;
+        let enter = hits.into_iter().next()?;
+        (enter.record.t < exit.record.t).then_some(Self { enter, exit })
+    }
+}
+
 impl HitRecord {
     pub fn from_outward_normal(t: f64, point: Point3, ray: Vec3, out_normal: Vec3) -> Self {
         let front_face = ray.dot(out_normal) < 0.0;
@@ -112,6 +366,12 @@ impl HitRecord {
             t,
             front_face,
             normal,
+            u: 0.0,
+            v: 0.0,
         }
     }
+
+    pub fn with_uv(self, u: f64, v: f64) -> Self {
+        Self { u, v, ..self }
+    }
 }
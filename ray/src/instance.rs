@@ -0,0 +1,240 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// A hittable placed in the world by a transform. The hittable itself is shared, so any number of
+// instances can reuse the same geometry.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    // from the object's space to the world
+    transform: Transform,
+    bbox: Option<Aabb>,
+}
+
+// A pose of an animated instance: a scale, then a rotation and then a translation
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f64,
+    pub scale: Vec3,
+    pub rotation: Quaternion,
+    pub translation: Vec3,
+}
+
+// A hittable moving through the world, posed by keyframes. Between two keyframes the scale and
+// translation are interpolated linearly and the rotation spherically. The object holds still
+// before the first keyframe and after the last one.
+pub struct AnimatedInstance {
+    object: Arc<dyn Hittable>,
+    // in order of time
+    keyframes: Vec<Keyframe>,
+    // encloses the object over the whole animation
+    bbox: Option<Aabb>,
+}
+
+impl Instance {
+    pub fn new(object: Arc<dyn Hittable>, transform: Transform) -> Arc<Self> {
+        let bbox = object
+            .bounding_box()
+            .map(|bbox| bbox.transformed(transform).padded());
+        Arc::new(Self {
+            object,
+            transform,
+            bbox,
+        })
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        let hit = self
+            .object
+            .hit(&local_ray(self.transform, ray), t_min, t_max, sampler)?;
+        Some(to_world(self.transform, hit))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    fn shadow_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        let local = local_ray(self.transform, ray);
+        let hit = self.object.shadow_hit(&local, t_min, t_max, sampler)?;
+        Some(to_world(self.transform, hit))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let local = local_ray(self.transform, ray);
+        self.object.transmittance(&local, t_min, t_max, sampler)
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let spans = self.object.spans(&local_ray(self.transform, ray))?;
+        Some(spans_to_world(self.transform, spans))
+    }
+}
+
+impl Keyframe {
+    pub fn new(time: f64, scale: Vec3, rotation: Quaternion, translation: Vec3) -> Self {
+        Self {
+            time,
+            scale,
+            rotation,
+            translation,
+        }
+    }
+
+    // the pose a fraction t of the way from self to next
+    fn lerp(self, t: f64, next: Keyframe) -> Keyframe {
+        Keyframe {
+            time: self.time + t * (next.time - self.time),
+            scale: self.scale.lerp(t, next.scale),
+            rotation: self.rotation.slerp(t, next.rotation),
+            translation: self.translation.lerp(t, next.translation),
+        }
+    }
+
+    fn transform(self) -> Transform {
+        Transform::scale(self.scale)
+            .then(Transform::orient(self.rotation))
+            .then(Transform::translate(self.translation))
+    }
+}
+
+impl AnimatedInstance {
+    // There must be at least one keyframe, and the scales must be non-zero. The scale along an
+    // axis mustn't change sign between keyframes either, or it would pass through zero.
+    pub fn new(object: Arc<dyn Hittable>, mut keyframes: Vec<Keyframe>) -> Arc<Self> {
+        assert!(!keyframes.is_empty());
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        assert!(keyframes.windows(2).all(
+            |pair| (0..3).all(|axis| pair[0].scale.axis(axis) * pair[1].scale.axis(axis) > 0.0)
+        ));
+
+        // The union of the object's box at enough instants that no point turns by more than a
+        // degree in between, padded by the most that a point can then stray from the chords.
+        let bbox = object.bounding_box().map(|bbox| {
+            let mut union = bbox.transformed(keyframes[0].transform());
+            for pair in keyframes.windows(2) {
+                let degrees = pair[0].rotation.angle_to(pair[1].rotation).to_degrees();
+                let steps = degrees.ceil().max(1.0) as usize;
+                for step in 1..=steps {
+                    let pose = pair[0].lerp(step as f64 / steps as f64, pair[1]);
+                    union = union.union(bbox.transformed(pose.transform()));
+                }
+            }
+            let pad = 1e-4 * union.extent().length();
+            let pad = Vec3::new(pad, pad, pad);
+            Aabb::new(union.min - pad, union.max + pad).padded()
+        });
+        Arc::new(Self {
+            object,
+            keyframes,
+            bbox,
+        })
+    }
+
+    fn transform(&self, time: f64) -> Transform {
+        let next = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 {
+            return self.keyframes[0].transform();
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1].transform();
+        }
+        let (a, b) = (self.keyframes[next - 1], self.keyframes[next]);
+        a.lerp((time - a.time) / (b.time - a.time), b).transform()
+    }
+}
+
+impl Hittable for AnimatedInstance {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        let transform = self.transform(ray.time);
+        let hit = self
+            .object
+            .hit(&local_ray(transform, ray), t_min, t_max, sampler)?;
+        Some(to_world(transform, hit))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+
+    fn shadow_hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        let transform = self.transform(ray.time);
+        let hit = self
+            .object
+            .shadow_hit(&local_ray(transform, ray), t_min, t_max, sampler)?;
+        Some(to_world(transform, hit))
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let transform = self.transform(ray.time);
+        self.object
+            .transmittance(&local_ray(transform, ray), t_min, t_max, sampler)
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let transform = self.transform(ray.time);
+        let spans = self.object.spans(&local_ray(transform, ray))?;
+        Some(spans_to_world(transform, spans))
+    }
+}
+
+// The ray in the object's space. The direction isn't normalized, so that distances along the ray
+// (t) are the same in both spaces.
+fn local_ray(transform: Transform, ray: &Ray) -> Ray {
+    let inverse = transform.inverse();
+    Ray::new(
+        inverse.point(ray.origin),
+        inverse.vector(ray.direction),
+        ray.time,
+    )
+}
+
+fn to_world(transform: Transform, mut hit: HitResult) -> HitResult {
+    let record = &mut hit.record;
+    record.point = transform.point(record.point);
+    // the normal already faces against the ray, which the normal transform preserves
+    record.normal = transform.normal(record.normal).as_unit();
+    hit
+}
+
+fn spans_to_world(transform: Transform, spans: Vec<Span>) -> Vec<Span> {
+    spans
+        .into_iter()
+        .map(|span| Span {
+            enter: to_world(transform, span.enter),
+            exit: to_world(transform, span.exit),
+        })
+        .collect()
+}
@@ -1,6 +1,23 @@
+pub mod aabb;
 pub mod bitmap;
+pub mod bvh;
+pub mod checkpoint;
+pub mod csg;
+pub mod environment;
+pub mod grid;
 pub mod hittable;
+pub mod instance;
+pub mod light;
 pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod obj;
+pub mod planar;
 mod ray;
 pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod solid;
+pub mod texture;
 pub mod vec;
+pub mod volume;
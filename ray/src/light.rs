@@ -0,0 +1,21 @@
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// A direction towards a light, picked by Light::sample
+pub struct LightSample {
+    // unit vector from the origin towards the light
+    pub direction: Vec3,
+    // solid angle density of having picked the direction
+    pub pdf: f64,
+}
+
+// A shape that the renderer can sample directly, to send shadow rays towards emitters instead of
+// waiting for scattered rays to find them. The radiance itself comes from the material of whatever
+// the shadow ray hits.
+pub trait Light: Send + Sync {
+    // picks a direction from origin towards the light, if the light is visible from there at all
+    fn sample(&self, origin: Point3, sampler: &mut dyn Sampler) -> Option<LightSample>;
+
+    // the solid angle density with which sample() picks direction from origin
+    fn pdf(&self, origin: Point3, direction: Vec3) -> f64;
+}
@@ -1,38 +1,156 @@
-use std::rc::Rc;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use rand::{random, Rng};
+use anyhow::{bail, Context};
+use clap::{Parser, ValueEnum};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use ray::{
-    bitmap::PPM,
+    bitmap::{BitDepth, Bitmap, PixelType, Serializer, EXR, PFM, PNG, PPM},
+    bvh::{BvhNode, SplitMethod},
+    checkpoint::{fingerprint, Checkpointing},
     hittable::*,
     material::*,
-    render::{Config, Raytracer},
+    planar::Plane,
+    render::{AdaptiveSampling, Config, Progression, Raytracer},
+    sampler::SamplerKind,
+    scene::Scene,
     vec::*,
 };
 
-fn random_scene() -> World {
+/// Renders a scene with a path tracer
+#[derive(Parser)]
+struct Args {
+    /// Scene file to render, instead of the built-in random scene
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1200)]
+    width: u32,
+
+    /// Image height in pixels [default: width at a 3:2 aspect ratio]
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Samples per pixel [default: 500, or the scene's setting]
+    #[arg(short, long)]
+    samples: Option<u32>,
+
+    /// Maximum number of bounces of a ray [default: the scene's setting]
+    #[arg(short, long)]
+    bounce_limit: Option<u32>,
+
+    /// Output image, with the format inferred from the extension (.ppm, .png, or .pfm and .exr
+    /// for linear HDR output) [default: PPM on stdout]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Bits per channel: 8 or 16 for PNG, 16 (half) or 32 (float) for EXR [default: 8 for PNG,
+    /// 16 for EXR]
+    #[arg(long, value_enum)]
+    bit_depth: Option<Depth>,
+
+    /// Seed of the random numbers of the render, and of the random scene, the same seed giving
+    /// the same image [default: the scene's setting, or 0]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How the random numbers of the render are spread over the samples of each pixel, the
+    /// low-discrepancy samplers giving less noise [default: the scene's setting, or independent]
+    #[arg(long, value_enum)]
+    sampler: Option<SamplerArg>,
+
+    /// Samples each pixel until the standard error of its displayed brightness (from 0 to 1)
+    /// falls below this, within the scene's adaptive sample bounds (16 to 1024 by default),
+    /// instead of taking the same number of samples everywhere
+    #[arg(long)]
+    noise_threshold: Option<f64>,
+
+    /// Also writes a heat map of the number of samples each pixel took, in the same formats as
+    /// the output
+    #[arg(long)]
+    heatmap: Option<PathBuf>,
+
+    /// Renders in passes of doubling samples per pixel, writing the image so far to the output
+    /// file after every pass
+    #[arg(long)]
+    progressive: bool,
+
+    /// Stops a progressive render after this many seconds, even if not all samples were taken
+    /// (implies --progressive)
+    #[arg(long)]
+    time_budget: Option<f64>,
+
+    /// Saves the progress of the render to this file every so often, to resume it from with
+    /// --resume if it is stopped (implies --progressive)
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Seconds between checkpoints
+    #[arg(long, default_value_t = 600.0)]
+    checkpoint_interval: f64,
+
+    /// Picks up the render from the checkpoint file if there is one, which must be of the same
+    /// scene and settings
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Number of render threads, 0 uses all available cores
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Depth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+    #[value(name = "32")]
+    ThirtyTwo,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SamplerArg {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+enum OutputFormat {
+    Ppm,
+    Png(BitDepth),
+    Pfm,
+    Exr(PixelType),
+}
+
+fn random_scene(rng: &mut impl Rng) -> World {
     let mut world = World::new();
 
     let ground_mat = ApproxLambertian::new(Color::new(0.5, 0.5, 0.5));
-    world.add(Sphere::new(
-        Point3::new(0.0, -1000, 0),
-        1000.0,
+    world.add(Plane::new(
+        Point3::ZERO,
+        Vec3::new(0.0, 1.0, 0.0),
         ground_mat.clone(),
     ));
 
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = random::<f64>();
+            let choose_mat = rng.gen::<f64>();
             let center = Vec3::new(
-                a as f64 + 0.9 * random::<f64>(),
+                a as f64 + 0.9 * rng.gen::<f64>(),
                 0.2,
-                b as f64 + 0.9 * random::<f64>(),
+                b as f64 + 0.9 * rng.gen::<f64>(),
             );
 
             if (center - Point3::new(4, 0.2, 0)).length() > 0.9 {
-                let rng = &mut rand::thread_rng();
-                let sphere_material: Rc<dyn Material> = if choose_mat < 0.8 {
+                let sphere_material: Arc<dyn Material> = if choose_mat < 0.8 {
                     // diffuse
-                    let albedo = random::<Color>() * random::<Color>();
+                    let albedo = rng.gen::<Color>() * rng.gen::<Color>();
                     ApproxLambertian::new(albedo)
                 } else if choose_mat < 0.95 {
                     // metal
@@ -57,29 +175,167 @@ fn random_scene() -> World {
     let material3 = Metal::new(Color::new(0.7, 0.6, 0.5), 0.0);
     world.add(Sphere::new(Point3::new(4, 1, 0), 1.0, material3));
 
-    return world;
+    world
 }
 
 fn main() -> anyhow::Result<()> {
-    let size = Size::from_aspect_ratio(1200, 3.0 / 2.0);
+    let args = Args::parse();
+    let format = output_format(args.output.as_deref(), args.bit_depth)?;
+    let heatmap_format = args
+        .heatmap
+        .as_deref()
+        .map(|path| output_format(Some(path), None))
+        .transpose()?;
+
+    let (world, mut config, lights, scene_fingerprint) = match &args.scene {
+        Some(path) => {
+            let scene = Scene::load(path)?;
+            (scene.world, scene.config, scene.lights, scene.fingerprint)
+        }
+        None => {
+            let seed = args.seed.unwrap_or(0);
+            let world = random_scene(&mut StdRng::seed_from_u64(seed));
+            // the seed of the random scene is that of the config
+            (world, random_scene_config(), vec![], fingerprint(b"random"))
+        }
+    };
+    if let Some(samples) = args.samples {
+        config.samples_per_pixel = samples;
+    }
+    if let Some(bounce_limit) = args.bounce_limit {
+        config.bounce_limit = bounce_limit;
+    }
+    if let Some(seed) = args.seed {
+        config.seed = seed;
+    }
+    if let Some(sampler) = args.sampler {
+        config.sampler = match sampler {
+            SamplerArg::Independent => SamplerKind::Independent,
+            SamplerArg::Stratified => SamplerKind::Stratified,
+            SamplerArg::Halton => SamplerKind::Halton,
+            SamplerArg::Sobol => SamplerKind::Sobol,
+        };
+    }
+    if let Some(noise_threshold) = args.noise_threshold {
+        if noise_threshold <= 0.0 {
+            bail!("the noise threshold must be positive");
+        }
+        config.adaptive = Some(AdaptiveSampling {
+            noise_threshold,
+            ..config.adaptive.unwrap_or_default()
+        });
+    }
+    let time_budget = match args.time_budget {
+        Some(seconds) if !(seconds > 0.0 && seconds.is_finite()) => {
+            bail!("the time budget must be a positive number of seconds")
+        }
+        budget => budget.map(Duration::from_secs_f64),
+    };
+    if !(args.checkpoint_interval > 0.0 && args.checkpoint_interval.is_finite()) {
+        bail!("the checkpoint interval must be a positive number of seconds");
+    }
+    let checkpointing = args.checkpoint.as_ref().map(|path| Checkpointing {
+        path: path.clone(),
+        interval: Duration::from_secs_f64(args.checkpoint_interval),
+        scene: scene_fingerprint,
+        resume: args.resume,
+    });
+    config.threads = args.threads;
+    config.linear_output = matches!(format, OutputFormat::Pfm | OutputFormat::Exr(_));
 
-    let world = random_scene();
+    let size = match args.height {
+        Some(height) => Size::new(args.width, height),
+        None => Size::from_aspect_ratio(args.width, 3.0 / 2.0),
+    };
+    let raytracer =
+        Raytracer::new(config, BvhNode::from_world(world, SplitMethod::Sah)).with_lights(lights);
 
-    let lookfrom = Point3::new(13, 2, 3);
-    let lookto = Point3::new(0, 0, 0);
-    let config = Config {
-        lookfrom,
-        lookto,
+    let (bitmap, heatmap) = if args.progressive || time_budget.is_some() || checkpointing.is_some()
+    {
+        let progression = Progression {
+            target_samples: None,
+            time_budget,
+            checkpointing,
+        };
+        let snapshot = raytracer.render_progressive(size, progression, |snapshot| {
+            // previews of stdout output would be concatenated, so only files get them
+            let Some(path) = &args.output else {
+                return;
+            };
+            if let Err(error) = write_file(path, |target| save(&snapshot.bitmap, &format, target)) {
+                eprintln!("failed to write preview: {error:#}");
+            }
+        })?;
+        (snapshot.bitmap, snapshot.heatmap)
+    } else {
+        raytracer.render_with_heatmap(size)
+    };
+    if let (Some(path), Some(format)) = (&args.heatmap, &heatmap_format) {
+        write_file(path, |target| save(&heatmap, format, target))?;
+    }
+    match &args.output {
+        Some(path) => write_file(path, |target| save(&bitmap, &format, target)),
+        None => save(&bitmap, &format, &mut std::io::stdout().lock()),
+    }
+}
+
+fn random_scene_config() -> Config {
+    Config {
+        lookfrom: Point3::new(13, 2, 3),
+        lookto: Point3::new(0, 0, 0),
         vertical_fov: 20f64.to_radians(),
         aperture: 0.1,
         focus_dist: 10.0,
         samples_per_pixel: 500,
         ..Default::default()
+    }
+}
+
+fn output_format(path: Option<&Path>, bit_depth: Option<Depth>) -> anyhow::Result<OutputFormat> {
+    let Some(path) = path else {
+        return Ok(OutputFormat::Ppm);
     };
-    let raytracer = Raytracer::new(config, Rc::new(world));
 
-    let bitmap = raytracer.render(size);
-    PPM.save(&bitmap, &mut std::io::stdout())?;
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let format = match (extension.map(str::to_ascii_lowercase).as_deref(), bit_depth) {
+        (Some("ppm"), None | Some(Depth::Eight)) => OutputFormat::Ppm,
+        (Some("png"), None | Some(Depth::Eight)) => OutputFormat::Png(BitDepth::Eight),
+        (Some("png"), Some(Depth::Sixteen)) => OutputFormat::Png(BitDepth::Sixteen),
+        (Some("pfm"), None | Some(Depth::ThirtyTwo)) => OutputFormat::Pfm,
+        (Some("exr"), None | Some(Depth::Sixteen)) => OutputFormat::Exr(PixelType::Half),
+        (Some("exr"), Some(Depth::ThirtyTwo)) => OutputFormat::Exr(PixelType::Float),
+        (Some("ppm" | "png" | "pfm" | "exr"), Some(_)) => {
+            bail!("unsupported bit depth for {}", path.display())
+        }
+        _ => bail!("unsupported output format: {}", path.display()),
+    };
+    Ok(format)
+}
+
+fn save(bitmap: &Bitmap, format: &OutputFormat, target: &mut impl Write) -> anyhow::Result<()> {
+    match *format {
+        OutputFormat::Ppm => PPM.save(bitmap, target),
+        OutputFormat::Png(depth) => PNG::new(depth).save(bitmap, target),
+        OutputFormat::Pfm => PFM.save(bitmap, target),
+        OutputFormat::Exr(pixel_type) => EXR::new(pixel_type).save(bitmap, target),
+    }
+}
+
+// Writes through a temporary file next to path, so that a render killed while writing (e.g. a
+// preview) leaves the previous file whole.
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
 
-    Result::Ok(())
+    let file = File::create(&temporary)
+        .with_context(|| format!("failed to create {}", temporary.display()))?;
+    let mut target = BufWriter::new(file);
+    write(&mut target)?;
+    target.flush()?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("failed to replace {}", path.display()))
 }
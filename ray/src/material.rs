@@ -1,10 +1,10 @@
-use std::rc::Rc;
-
-use rand::random;
+use std::sync::Arc;
 
 use crate::{
     hittable::HitRecord,
     ray::Ray,
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
     vec::{Color, Vec3},
 };
 
@@ -13,16 +13,36 @@ pub enum ScatterResult {
     Scattered { scattered: Ray, attenuation: Color },
 }
 
-pub trait Material {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> ScatterResult;
+// Materials that can be evaluated for any pair of directions (not only sampled through scatter)
+// implement eval and pdf, which lets the renderer sample lights directly. For those, the
+// attenuation of a scattered ray must equal eval / pdf in its direction. Perfectly specular
+// materials keep the defaults, a zero pdf marking the scattered ray as not evaluable.
+pub trait Material: Send + Sync {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult;
+
+    // the BSDF times the cosine of the angle to the normal (or the phase function, for media),
+    // for light arriving along direction
+    fn eval(&self, _ray: &Ray, _hit: &HitRecord, _direction: Vec3) -> Color {
+        Color::ZERO
+    }
+
+    // the solid angle density with which scatter picks direction
+    fn pdf(&self, _ray: &Ray, _hit: &HitRecord, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // radiance emitted by the surface towards the ray
+    fn emitted(&self, _ray: &Ray, _hit: &HitRecord) -> Color {
+        Color::ZERO
+    }
 }
 
 pub struct ApproxLambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
@@ -31,33 +51,70 @@ pub struct Dielectric {
 }
 
 pub struct AltLambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
+}
+
+// An area light emitting uniformly in all directions, optionally only from its front face
+pub struct DiffuseLight {
+    radiance: Color,
+    one_sided: bool,
+}
+
+// The phase function of a participating medium scattering equally in all directions, with the
+// albedo being the fraction of the light not absorbed at each scattering
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+// The Henyey-Greenstein phase function, which scatters light mostly forward for an anisotropy g
+// towards 1 (as in haze) and mostly back towards -1, being isotropic at 0
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f64,
 }
 
 impl ApproxLambertian {
-    pub fn new(albedo: Color) -> Rc<Self> {
-        Rc::new(Self { albedo })
+    pub fn new(albedo: Color) -> Arc<Self> {
+        Self::textured(SolidColor::new(albedo))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Arc<Self> {
+        Arc::new(Self { albedo })
     }
 }
 
 impl Material for ApproxLambertian {
-    fn scatter(&self, _: &Ray, hit: &HitRecord) -> ScatterResult {
-        let mut direction = hit.normal + Vec3::random_unit_vector();
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
+        let mut direction = hit.normal + Vec3::random_unit_vector(sampler);
         // check for degenerate scatter condition (if the normal and the random vector are exactly opposite)
         if direction.is_near_zero() {
             direction = hit.normal;
         }
-        let ray = Ray::new(hit.point, direction);
+        let ray = Ray::new(hit.point, direction, ray.time);
         ScatterResult::Scattered {
             scattered: ray,
-            attenuation: self.albedo,
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
         }
     }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.pdf(ray, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    // the scattered direction is cosine-distributed about the normal
+    fn pdf(&self, _: &Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        let cosine = direction.as_unit().dot(hit.normal);
+        cosine.max(0.0) / std::f64::consts::PI
+    }
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Rc<Self> {
-        Rc::new(Self {
+    pub fn new(albedo: Color, fuzz: f64) -> Arc<Self> {
+        Self::textured(SolidColor::new(albedo), fuzz)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, fuzz: f64) -> Arc<Self> {
+        Arc::new(Self {
             albedo,
             fuzz: fuzz.min(1.0),
         })
@@ -65,24 +122,24 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> ScatterResult {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
         let reflected = ray.direction.as_unit().reflect(hit.normal);
-        let direction = reflected + self.fuzz * Vec3::random_unit_vector();
+        let direction = reflected + self.fuzz * Vec3::random_unit_vector(sampler);
         if direction.dot(hit.normal) < 0.0 {
             return ScatterResult::Absorbed;
         }
 
-        let ray = Ray::new(hit.point, direction);
+        let ray = Ray::new(hit.point, direction, ray.time);
         ScatterResult::Scattered {
             scattered: ray,
-            attenuation: self.albedo,
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
         }
     }
 }
 
 impl Dielectric {
-    pub fn new(refraction_index: f64) -> Rc<Self> {
-        Rc::new(Self { refraction_index })
+    pub fn new(refraction_index: f64) -> Arc<Self> {
+        Arc::new(Self { refraction_index })
     }
 
     fn reflectance(cosine: f64, refraction_ratio: f64) -> f64 {
@@ -94,7 +151,7 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> ScatterResult {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
         let refraction_ratio = if hit.front_face {
             1.0 / self.refraction_index
         } else {
@@ -106,14 +163,14 @@ impl Material for Dielectric {
         let sine = (1.0 - cosine * cosine).sqrt();
 
         let cannot_refract = refraction_ratio * sine > 1.0;
-        let direction = if cannot_refract || Self::reflectance(cosine, refraction_ratio) > random()
-        {
-            unit_direction.reflect(hit.normal)
-        } else {
-            unit_direction.refract(hit.normal, refraction_ratio)
-        };
+        let direction =
+            if cannot_refract || Self::reflectance(cosine, refraction_ratio) > sampler.get_1d() {
+                unit_direction.reflect(hit.normal)
+            } else {
+                unit_direction.refract(hit.normal, refraction_ratio)
+            };
 
-        let ray = Ray::new(hit.point, direction);
+        let ray = Ray::new(hit.point, direction, ray.time);
 
         let attenuation = Color::new(1.0, 1.0, 1.0);
         ScatterResult::Scattered {
@@ -124,14 +181,18 @@ impl Material for Dielectric {
 }
 
 impl AltLambertian {
-    pub fn new(albedo: Color) -> Rc<Self> {
-        Rc::new(Self { albedo })
+    pub fn new(albedo: Color) -> Arc<Self> {
+        Self::textured(SolidColor::new(albedo))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Arc<Self> {
+        Arc::new(Self { albedo })
     }
 }
 
 impl Material for AltLambertian {
-    fn scatter(&self, _: &Ray, hit: &HitRecord) -> ScatterResult {
-        let in_unit_sphere = Vec3::random_unit_vector();
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
+        let in_unit_sphere = Vec3::random_unit_vector(sampler);
         let mut direction = if in_unit_sphere.dot(hit.normal) > 0.0 {
             // In the same hemisphere as the normal
             hit.point + in_unit_sphere
@@ -142,10 +203,125 @@ impl Material for AltLambertian {
         if direction.is_near_zero() {
             direction = hit.normal;
         }
-        let ray = Ray::new(hit.point, direction - hit.point);
+        let ray = Ray::new(hit.point, direction - hit.point, ray.time);
         ScatterResult::Scattered {
             scattered: ray,
-            attenuation: self.albedo,
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
+        }
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.pdf(ray, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    // the scattered direction is uniformly distributed over the hemisphere
+    fn pdf(&self, _: &Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        if direction.dot(hit.normal) <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (2.0 * std::f64::consts::PI)
+    }
+}
+
+impl DiffuseLight {
+    pub fn new(color: Color, intensity: f64) -> Arc<Self> {
+        Arc::new(Self {
+            radiance: intensity * color,
+            one_sided: false,
+        })
+    }
+
+    pub fn one_sided(color: Color, intensity: f64) -> Arc<Self> {
+        Arc::new(Self {
+            radiance: intensity * color,
+            one_sided: true,
+        })
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn Sampler) -> ScatterResult {
+        ScatterResult::Absorbed
+    }
+
+    fn emitted(&self, _: &Ray, hit: &HitRecord) -> Color {
+        if self.one_sided && !hit.front_face {
+            return Color::ZERO;
         }
+        self.radiance
+    }
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Arc<Self> {
+        Self::textured(SolidColor::new(albedo))
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>) -> Arc<Self> {
+        Arc::new(Self { albedo })
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
+        ScatterResult::Scattered {
+            scattered: Ray::new(hit.point, Vec3::random_unit_vector(sampler), ray.time),
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
+        }
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.pdf(ray, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    fn pdf(&self, _: &Ray, _: &HitRecord, _: Vec3) -> f64 {
+        1.0 / (4.0 * std::f64::consts::PI)
+    }
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> Arc<Self> {
+        Self::textured(SolidColor::new(albedo), g)
+    }
+
+    pub fn textured(albedo: Arc<dyn Texture>, g: f64) -> Arc<Self> {
+        assert!(g.abs() < 1.0, "anisotropy must be between -1 and 1");
+        Arc::new(Self { albedo, g })
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    // inverts the cumulative distribution of the cosine of the angle between the incoming and
+    // scattered directions
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
+        let g = self.g;
+        let (xi, xi_phi) = sampler.get_2d();
+        let cosine = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * xi_phi;
+
+        let forward = ray.direction.as_unit();
+        let (s, t) = forward.basis();
+        let direction = cosine * forward + sine * (phi.cos() * s + phi.sin() * t);
+        ScatterResult::Scattered {
+            scattered: Ray::new(hit.point, direction, ray.time),
+            attenuation: self.albedo.value(hit.u, hit.v, hit.point),
+        }
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.pdf(ray, hit, direction) * self.albedo.value(hit.u, hit.v, hit.point)
+    }
+
+    fn pdf(&self, ray: &Ray, _: &HitRecord, direction: Vec3) -> f64 {
+        let g = self.g;
+        let cosine = ray.direction.as_unit().dot(direction.as_unit());
+        let denominator = 1.0 + g * g - 2.0 * g * cosine;
+        (1.0 - g * g) / (4.0 * std::f64::consts::PI * denominator * denominator.sqrt())
     }
 }
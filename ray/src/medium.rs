@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+
+// A participating medium of constant density (such as smoke) filling a closed boundary. Rays
+// passing through it are scattered at random distances, according to the phase function given as
+// its material (e.g. Isotropic), while the boundary itself is invisible.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    // the chance of scattering per unit distance
+    density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+// A homogeneous medium filling the whole scene, or only the part of it below a height, for
+// distance fog and haze. It is applied by the renderer rather than being part of the world.
+pub struct Atmosphere {
+    density: f64,
+    // the top of the fog, unbounded if None
+    height: Option<f64>,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            boundary,
+            density,
+            phase_function,
+        })
+    }
+
+    // The intervals of the ray inside the boundary. Boundaries that don't report spans are taken
+    // to be convex, between the first hit and the next one.
+    fn intervals(&self, ray: &Ray, sampler: &mut dyn Sampler) -> Vec<(f64, f64)> {
+        if let Some(spans) = self.boundary.spans(ray) {
+            return spans
+                .iter()
+                .map(|span| (span.enter.record.t, span.exit.record.t))
+                .collect();
+        }
+        let Some(enter) = self
+            .boundary
+            .hit(ray, f64::NEG_INFINITY, f64::INFINITY, sampler)
+        else {
+            return vec![];
+        };
+        let enter = enter.record.t;
+        match self
+            .boundary
+            .hit(ray, enter + 0.0001, f64::INFINITY, sampler)
+        {
+            Some(exit) => vec![(enter, exit.record.t)],
+            None => vec![],
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        let intervals = self
+            .intervals(ray, sampler)
+            .into_iter()
+            .map(|(enter, exit)| (enter.max(t_min), exit.min(t_max)));
+        let t = free_flight(ray, self.density, intervals, sampler)?;
+        medium_hit(ray, t, &self.phase_function)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+
+    fn shadow_hit(&self, _: &Ray, _: f64, _: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        None
+    }
+
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let inside: f64 = self
+            .intervals(ray, sampler)
+            .into_iter()
+            .map(|(enter, exit)| (exit.min(t_max) - enter.max(t_min)).max(0.0))
+            .sum();
+        (-self.density * inside * ray.direction.length()).exp()
+    }
+}
+
+impl Atmosphere {
+    pub fn new(density: f64, height: Option<f64>, phase_function: Arc<dyn Material>) -> Self {
+        Self {
+            density,
+            height,
+            phase_function,
+        }
+    }
+
+    // the interval of the ray up to t_max which lies in the fog
+    fn interval(&self, ray: &Ray, t_max: f64) -> (f64, f64) {
+        let Some(height) = self.height else {
+            return (0.0, t_max);
+        };
+        let (y, dy) = (ray.origin.y(), ray.direction.y());
+        if dy == 0.0 {
+            return if y <= height {
+                (0.0, t_max)
+            } else {
+                (0.0, 0.0)
+            };
+        }
+        let crossing = (height - y) / dy;
+        if dy > 0.0 {
+            (0.0, crossing.min(t_max))
+        } else {
+            (crossing.max(0.0), t_max)
+        }
+    }
+
+    // Picks where the ray scatters in the fog before reaching t_max, if it does.
+    pub fn scatter(&self, ray: &Ray, t_max: f64, sampler: &mut dyn Sampler) -> Option<HitResult> {
+        let t = free_flight(ray, self.density, [self.interval(ray, t_max)], sampler)?;
+        medium_hit(ray, t, &self.phase_function)
+    }
+
+    // the fraction of light passing through the fog along the ray up to t_max
+    pub fn transmittance(&self, ray: &Ray, t_max: f64) -> f64 {
+        let (enter, exit) = self.interval(ray, t_max);
+        if exit <= enter {
+            return 1.0;
+        }
+        (-self.density * (exit - enter) * ray.direction.length()).exp()
+    }
+}
+
+// Samples the distance a ray travels through a medium of the given density before scattering,
+// going through the intervals of the ray that are inside the medium in order. Returns the t where
+// it scatters, or None if it makes it through all of them.
+fn free_flight(
+    ray: &Ray,
+    density: f64,
+    intervals: impl IntoIterator<Item = (f64, f64)>,
+    sampler: &mut dyn Sampler,
+) -> Option<f64> {
+    let length = ray.direction.length();
+    let mut distance = -(1.0 - sampler.get_1d()).ln() / density;
+    for (enter, exit) in intervals {
+        if exit <= enter {
+            continue;
+        }
+        let inside = (exit - enter) * length;
+        if distance < inside {
+            return Some(enter + distance / length);
+        }
+        distance -= inside;
+    }
+    None
+}
+
+// a scattering event inside a medium, which has no surface and so no meaningful normal
+fn medium_hit(ray: &Ray, t: f64, phase_function: &Arc<dyn Material>) -> Option<HitResult> {
+    let record = HitRecord {
+        point: ray.at(t),
+        t,
+        normal: -ray.direction.as_unit(),
+        front_face: true,
+        u: 0.0,
+        v: 0.0,
+    };
+    HitResult::new(record, Arc::clone(phase_function))
+}
@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::{BvhNode, SplitMethod};
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// A single triangle, optionally with per-vertex normals for smooth shading
+#[derive(Clone)]
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    pub normals: Option<[Vec3; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+// A corner of a mesh face, given as indices into the mesh's attribute buffers
+#[derive(Clone, Copy, Debug)]
+pub struct MeshVertex {
+    pub position: usize,
+    pub normal: Option<usize>,
+    pub uv: Option<usize>,
+}
+
+// The shared buffers of a triangle mesh
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub faces: Vec<[MeshVertex; 3]>,
+    pub material: Arc<dyn Material>,
+}
+
+// An indexed triangle mesh, with its faces organized in a BVH
+pub struct TriangleMesh {
+    data: Arc<MeshData>,
+    bvh: Arc<dyn Hittable>,
+}
+
+// One face of a mesh, referring back to the shared buffers
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+// Barycentric coordinates of a ray-triangle intersection, (1 - b1 - b2, b1, b2)
+struct Barycentric {
+    t: f64,
+    b1: f64,
+    b2: f64,
+}
+
+// Möller–Trumbore ray-triangle intersection
+fn intersect(ray: &Ray, [p0, p1, p2]: [Point3; 3], t_min: f64, t_max: f64) -> Option<Barycentric> {
+    const EPSILON: f64 = 1e-12;
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = ray.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    // the ray is parallel to the triangle
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.origin - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(edge1);
+    let b2 = ray.direction.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+    Some(Barycentric { t, b1, b2 })
+}
+
+fn bounds([p0, p1, p2]: [Point3; 3]) -> Aabb {
+    Aabb::new(p0, p1).union(Aabb::new(p2, p2)).padded()
+}
+
+impl Barycentric {
+    fn interpolate(&self, [a, b, c]: [Vec3; 3]) -> Vec3 {
+        (1.0 - self.b1 - self.b2) * a + self.b1 * b + self.b2 * c
+    }
+
+    // The hit record with either the interpolated shading normal or the geometric normal, and
+    // either the interpolated texture coordinates or the barycentric coordinates themselves.
+    fn record(
+        &self,
+        ray: &Ray,
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+    ) -> HitRecord {
+        let out_normal = match normals {
+            Some(normals) => self.interpolate(normals),
+            None => (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]),
+        }
+        .as_unit();
+        let (u, v) = match uvs {
+            Some(uvs) => {
+                let uv = self.interpolate(uvs.map(|(u, v)| Vec3::new(u, v, 0.0)));
+                (uv.x(), uv.y())
+            }
+            None => (self.b1, self.b2),
+        };
+        HitRecord::from_outward_normal(self.t, ray.at(self.t), ray.direction, out_normal)
+            .with_uv(u, v)
+    }
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point3; 3], material: Arc<dyn Material>) -> Arc<Self> {
+        Arc::new(Self {
+            vertices,
+            normals: None,
+            material,
+        })
+    }
+
+    pub fn with_normals(
+        vertices: [Point3; 3],
+        normals: [Vec3; 3],
+        material: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            vertices,
+            normals: Some(normals),
+            material,
+        })
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let hit = intersect(ray, self.vertices, t_min, t_max)?;
+        HitResult::new(
+            hit.record(ray, self.vertices, self.normals, None),
+            Arc::clone(&self.material),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(bounds(self.vertices))
+    }
+}
+
+impl TriangleMesh {
+    // Panics if a face refers to a vertex attribute that is out of bounds.
+    pub fn new(data: MeshData) -> Arc<Self> {
+        for vertex in data.faces.iter().flatten() {
+            assert!(vertex.position < data.positions.len());
+            assert!(vertex.normal.is_none_or(|i| i < data.normals.len()));
+            assert!(vertex.uv.is_none_or(|i| i < data.uvs.len()));
+        }
+
+        let data = Arc::new(data);
+        let mut triangles = World::new();
+        for face in 0..data.faces.len() {
+            triangles.add(Arc::new(MeshTriangle {
+                mesh: Arc::clone(&data),
+                face,
+            }));
+        }
+        let bvh = BvhNode::from_world(triangles, SplitMethod::Sah);
+        Arc::new(Self { data, bvh })
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        self.bvh.hit(ray, t_min, t_max, sampler)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> [Point3; 3] {
+        self.mesh.faces[self.face].map(|vertex| self.mesh.positions[vertex.position])
+    }
+
+    // smooth shading requires a normal on every corner of the face
+    fn normals(&self) -> Option<[Vec3; 3]> {
+        let [a, b, c] = self.mesh.faces[self.face].map(|vertex| vertex.normal);
+        Some([a?, b?, c?].map(|i| self.mesh.normals[i]))
+    }
+
+    fn uvs(&self) -> Option<[(f64, f64); 3]> {
+        let [a, b, c] = self.mesh.faces[self.face].map(|vertex| vertex.uv);
+        Some([a?, b?, c?].map(|i| self.mesh.uvs[i]))
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let vertices = self.vertices();
+        let hit = intersect(ray, vertices, t_min, t_max)?;
+        HitResult::new(
+            hit.record(ray, vertices, self.normals(), self.uvs()),
+            Arc::clone(&self.mesh.material),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(bounds(self.vertices()))
+    }
+}
@@ -0,0 +1,303 @@
+// Wavefront OBJ/MTL loading.
+//
+// Only the polygonal subset of OBJ is supported: vertices (v), normals (vn), texture coordinates
+// (vt) and faces (f), with n-gons triangulated as fans. Materials are mapped onto the closest
+// material the renderer has:
+//
+// - emissive materials (a non-zero Ke) become a DiffuseLight with radiance Ke
+// - transparent materials (d < 1, or illum 4, 6, 7 or 9) become a Dielectric with index Ni
+// - reflective materials (illum 3, 5 or 8) become a Metal with albedo Ks, and the fuzz derived
+//   from the specular exponent Ns
+// - everything else is an ApproxLambertian with albedo Kd
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::checkpoint::Fingerprint;
+use crate::material::*;
+use crate::mesh::*;
+use crate::vec::*;
+
+// Loads the OBJ file at path, returning one mesh per material used. Faces that appear before
+// any `usemtl` statement, or that refer to an unknown material, get the default material. The
+// OBJ and MTL files read are added to files.
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    default_material: Arc<dyn Material>,
+    files: &mut Fingerprint,
+) -> Result<Vec<Arc<TriangleMesh>>> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    files.add(path, source.as_bytes());
+
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut materials: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    // faces grouped by the name of their material, in order of first use
+    let mut groups: Vec<(Option<String>, Vec<[MeshVertex; 3]>)> = vec![(None, vec![])];
+    let mut current_group = 0;
+
+    for (line_no, line) in lines(&source) {
+        let error = || format!("{}:{}", path.display(), line_no);
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&mut tokens).with_context(error)?),
+            "vn" => normals.push(parse_vec3(&mut tokens).with_context(error)?),
+            "vt" => {
+                let u = parse_f64(tokens.next()).with_context(error)?;
+                let v = tokens.next().map_or(Ok(0.0), |v| parse_f64(Some(v)));
+                uvs.push((u, v.with_context(error)?));
+            }
+            "f" => {
+                let counts = (positions.len(), normals.len(), uvs.len());
+                let polygon = tokens
+                    .map(|vertex| parse_face_vertex(vertex, counts))
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(error)?;
+                if polygon.len() < 3 {
+                    return Err(anyhow!("face with fewer than 3 vertices")).with_context(error);
+                }
+                let faces = &mut groups[current_group].1;
+                for i in 1..polygon.len() - 1 {
+                    faces.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            "usemtl" => {
+                let name = tokens.next().map(str::to_owned);
+                current_group = match groups.iter().position(|(group, _)| *group == name) {
+                    Some(index) => index,
+                    None => {
+                        groups.push((name, vec![]));
+                        groups.len() - 1
+                    }
+                };
+            }
+            "mtllib" => {
+                for file in tokens {
+                    let mtl_path = path
+                        .parent()
+                        .map_or(PathBuf::from(file), |dir| dir.join(file));
+                    materials.extend(load_mtl(&mtl_path, files).with_context(error)?);
+                }
+            }
+            // objects, groups and smoothing groups don't affect the meshes we build
+            _ => {}
+        }
+    }
+
+    let meshes = groups
+        .into_iter()
+        .filter(|(_, faces)| !faces.is_empty())
+        .map(|(name, faces)| {
+            let material = name
+                .and_then(|name| materials.get(&name).cloned())
+                .unwrap_or_else(|| Arc::clone(&default_material));
+            compact(&positions, &normals, &uvs, faces, material)
+        })
+        .collect();
+    Ok(meshes)
+}
+
+// Parses the MTL file at path into materials keyed by name, adding the file to files.
+pub fn load_mtl(
+    path: impl AsRef<Path>,
+    files: &mut Fingerprint,
+) -> Result<HashMap<String, Arc<dyn Material>>> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    files.add(path, source.as_bytes());
+
+    let mut definitions: Vec<(String, MtlDefinition)> = vec![];
+    for (line_no, line) in lines(&source) {
+        let error = || format!("{}:{}", path.display(), line_no);
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens
+                .next()
+                .ok_or_else(|| anyhow!("newmtl without a name"))
+                .with_context(error)?;
+            definitions.push((name.to_owned(), MtlDefinition::default()));
+            continue;
+        }
+        let Some((_, definition)) = definitions.last_mut() else {
+            return Err(anyhow!("`{keyword}` before any newmtl")).with_context(error);
+        };
+        match keyword {
+            "Kd" => definition.diffuse = parse_vec3(&mut tokens).with_context(error)?,
+            "Ks" => definition.specular = parse_vec3(&mut tokens).with_context(error)?,
+            "Ke" => definition.emission = parse_vec3(&mut tokens).with_context(error)?,
+            "Ns" => definition.exponent = parse_f64(tokens.next()).with_context(error)?,
+            "Ni" => definition.refraction_index = parse_f64(tokens.next()).with_context(error)?,
+            "d" => definition.dissolve = parse_f64(tokens.next()).with_context(error)?,
+            "Tr" => definition.dissolve = 1.0 - parse_f64(tokens.next()).with_context(error)?,
+            "illum" => {
+                definition.illum = parse_f64(tokens.next()).with_context(error)? as u32;
+            }
+            // texture maps and the other Phong terms have no equivalent
+            _ => {}
+        }
+    }
+
+    Ok(definitions
+        .into_iter()
+        .map(|(name, definition)| (name, definition.into_material()))
+        .collect())
+}
+
+struct MtlDefinition {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    exponent: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    illum: u32,
+}
+
+impl Default for MtlDefinition {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::ZERO,
+            emission: Color::ZERO,
+            exponent: 0.0,
+            refraction_index: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlDefinition {
+    fn into_material(self) -> Arc<dyn Material> {
+        if !self.emission.is_near_zero() {
+            DiffuseLight::new(self.emission, 1.0)
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Dielectric::new(self.refraction_index)
+        } else if matches!(self.illum, 3 | 5 | 8) {
+            // a rough equivalence between the Phong exponent and the width of the reflection lobe
+            let fuzz = (2.0 / (self.exponent + 2.0)).sqrt();
+            Metal::new(self.specular, fuzz)
+        } else {
+            ApproxLambertian::new(self.diffuse)
+        }
+    }
+}
+
+// Builds a mesh holding only the attributes referred to by the faces.
+fn compact(
+    positions: &[Point3],
+    normals: &[Vec3],
+    uvs: &[(f64, f64)],
+    mut faces: Vec<[MeshVertex; 3]>,
+    material: Arc<dyn Material>,
+) -> Arc<TriangleMesh> {
+    let mut data = MeshData {
+        positions: vec![],
+        normals: vec![],
+        uvs: vec![],
+        faces: vec![],
+        material,
+    };
+    let mut position_map = HashMap::new();
+    let mut normal_map = HashMap::new();
+    let mut uv_map = HashMap::new();
+
+    for vertex in faces.iter_mut().flatten() {
+        vertex.position = *position_map.entry(vertex.position).or_insert_with(|| {
+            data.positions.push(positions[vertex.position]);
+            data.positions.len() - 1
+        });
+        vertex.normal = vertex.normal.map(|normal| {
+            *normal_map.entry(normal).or_insert_with(|| {
+                data.normals.push(normals[normal]);
+                data.normals.len() - 1
+            })
+        });
+        vertex.uv = vertex.uv.map(|uv| {
+            *uv_map.entry(uv).or_insert_with(|| {
+                data.uvs.push(uvs[uv]);
+                data.uvs.len() - 1
+            })
+        });
+    }
+    data.faces = faces;
+    TriangleMesh::new(data)
+}
+
+// Non-empty lines with comments stripped, along with their 1-based line numbers
+fn lines(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.split('#').next().unwrap().trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+fn parse_f64(token: Option<&str>) -> Result<f64> {
+    let token = token.ok_or_else(|| anyhow!("missing number"))?;
+    token
+        .parse()
+        .with_context(|| format!("invalid number `{token}`"))
+}
+
+fn parse_vec3(tokens: &mut SplitWhitespace) -> Result<Vec3> {
+    Ok(Vec3::new(
+        parse_f64(tokens.next())?,
+        parse_f64(tokens.next())?,
+        parse_f64(tokens.next())?,
+    ))
+}
+
+// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face vertex, resolving negative (relative) indices
+// against the number of each attribute declared so far.
+fn parse_face_vertex(
+    token: &str,
+    (positions, normals, uvs): (usize, usize, usize),
+) -> Result<MeshVertex> {
+    let mut indices = token.split('/');
+    let position = resolve_index(indices.next(), positions)?
+        .ok_or_else(|| anyhow!("face vertex `{token}` has no position"))?;
+    let uv = resolve_index(indices.next(), uvs)?;
+    let normal = resolve_index(indices.next(), normals)?;
+    if indices.next().is_some() {
+        bail!("invalid face vertex `{token}`");
+    }
+    Ok(MeshVertex {
+        position,
+        normal,
+        uv,
+    })
+}
+
+fn resolve_index(token: Option<&str>, count: usize) -> Result<Option<usize>> {
+    let Some(token) = token.filter(|token| !token.is_empty()) else {
+        return Ok(None);
+    };
+    let index: i64 = token
+        .parse()
+        .with_context(|| format!("invalid index `{token}`"))?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => bail!("invalid index 0"),
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        bail!("index {index} is out of bounds");
+    }
+    Ok(Some(resolved as usize))
+}
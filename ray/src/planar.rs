@@ -0,0 +1,303 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::light::{Light, LightSample};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// A parallelogram spanned by the edges u and v from the corner q
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Arc<dyn Material>,
+    // unit normal, u x v
+    normal: Vec3,
+    // n / (n . n) for the unnormalized normal n, which maps a point of the plane to its (u, v)
+    // coordinates
+    w: Vec3,
+    area: f64,
+}
+
+// An infinite plane through point. Its (u, v) coordinates are distances along two axes of the
+// plane, so textures repeat across it every unit.
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+    // axes of the plane, completing the basis with the normal
+    axes: (Vec3, Vec3),
+}
+
+// A flat disk facing along normal, with (u, v) coordinates of the angle around it (as a fraction
+// of a turn) and the fraction of the radius out from its centre
+pub struct Disk {
+    pub centre: Point3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Arc<dyn Material>,
+    axes: (Vec3, Vec3),
+}
+
+// An axis-aligned box, made of six quads facing outwards
+pub struct AxisBox {
+    sides: Vec<Arc<Quad>>,
+    bbox: Aabb,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Arc<Self> {
+        let n = u.cross(v);
+        Arc::new(Self {
+            q,
+            u,
+            v,
+            material,
+            normal: n.as_unit(),
+            w: n / n.length_sq(),
+            area: n.length(),
+        })
+    }
+
+    // the ray parameter and the (u, v) coordinates of where the ray crosses the quad
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let t = plane_intersection(self.q, self.normal, ray, t_min, t_max)?;
+        let planar = ray.at(t) - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some((t, alpha, beta))
+    }
+
+    // the same as Hittable::hit, for surfaces that need no sampler
+    fn surface_hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitResult> {
+        let (t, alpha, beta) = self.intersect(ray, t_min, t_max)?;
+        HitResult::new(
+            HitRecord::from_outward_normal(t, ray.at(t), ray.direction, self.normal)
+                .with_uv(alpha, beta),
+            Arc::clone(&self.material),
+        )
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        self.surface_hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let diagonal = Aabb::new(self.q, self.q + self.u + self.v);
+        let anti_diagonal = Aabb::new(self.q + self.u, self.q + self.v);
+        Some(diagonal.union(anti_diagonal).padded())
+    }
+}
+
+impl Plane {
+    pub fn new(point: Point3, normal: Vec3, material: Arc<dyn Material>) -> Arc<Self> {
+        let normal = normal.as_unit();
+        Arc::new(Self {
+            point,
+            normal,
+            material,
+            axes: normal.basis(),
+        })
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let t = plane_intersection(self.point, self.normal, ray, t_min, t_max)?;
+        let point = ray.at(t);
+        let planar = point - self.point;
+        HitResult::new(
+            HitRecord::from_outward_normal(t, point, ray.direction, self.normal)
+                .with_uv(planar.dot(self.axes.0), planar.dot(self.axes.1)),
+            Arc::clone(&self.material),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+impl Disk {
+    pub fn new(
+        centre: Point3,
+        normal: Vec3,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        let normal = normal.as_unit();
+        Arc::new(Self {
+            centre,
+            normal,
+            radius,
+            material,
+            axes: normal.basis(),
+        })
+    }
+
+    fn area(&self) -> f64 {
+        std::f64::consts::PI * self.radius * self.radius
+    }
+
+    // the ray parameter of where the ray crosses the disk
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        let t = plane_intersection(self.centre, self.normal, ray, t_min, t_max)?;
+        ((ray.at(t) - self.centre).length_sq() <= self.radius * self.radius).then_some(t)
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let t = self.intersect(ray, t_min, t_max)?;
+        let point = ray.at(t);
+        let planar = point - self.centre;
+        let angle = planar.dot(self.axes.1).atan2(planar.dot(self.axes.0));
+        let u = angle.rem_euclid(2.0 * std::f64::consts::PI) / (2.0 * std::f64::consts::PI);
+        let v = planar.length() / self.radius;
+        HitResult::new(
+            HitRecord::from_outward_normal(t, point, ray.direction, self.normal).with_uv(u, v),
+            Arc::clone(&self.material),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // the disk reaches out along each axis as far as the axis lies within its plane
+        let n = self.normal;
+        let reach = |axis: f64| self.radius * (1.0 - axis * axis).max(0.0).sqrt();
+        let extent = Vec3::new(reach(n.x()), reach(n.y()), reach(n.z()));
+        Some(Aabb::new(self.centre - extent, self.centre + extent).padded())
+    }
+}
+
+impl Light for Disk {
+    fn sample(&self, origin: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        // uniform over the area
+        let (xi_r, xi_angle) = sampler.get_2d();
+        let r = self.radius * xi_r.sqrt();
+        let angle = 2.0 * std::f64::consts::PI * xi_angle;
+        let point = self.centre + r * (angle.cos() * self.axes.0 + angle.sin() * self.axes.1);
+        if (point - origin).is_near_zero() {
+            return None;
+        }
+        let direction = (point - origin).as_unit();
+        let pdf = self.pdf(origin, direction);
+        (pdf > 0.0).then_some(LightSample { direction, pdf })
+    }
+
+    fn pdf(&self, origin: Point3, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction, 0.0);
+        let Some(t) = self.intersect(&ray, 0.0, f64::INFINITY) else {
+            return 0.0;
+        };
+        let distance_sq = t * t * direction.length_sq();
+        let cosine = (direction.dot(self.normal) / direction.length()).abs();
+        if cosine < 1e-12 {
+            return 0.0;
+        }
+        distance_sq / (cosine * self.area())
+    }
+}
+
+impl AxisBox {
+    // the box spanned by two opposite corners, given in any order
+    pub fn new(a: Point3, b: Point3, material: Arc<dyn Material>) -> Arc<Self> {
+        let bbox = Aabb::new(a, b);
+        let (min, max) = (bbox.min, bbox.max);
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        let mut sides = vec![];
+        let mut side =
+            |q: Point3, u: Vec3, v: Vec3| sides.push(Quad::new(q, u, v, material.clone()));
+        // with u x v pointing out of the box
+        side(Point3::new(min.x(), min.y(), max.z()), dx, dy);
+        side(Point3::new(max.x(), min.y(), max.z()), -dz, dy);
+        side(Point3::new(max.x(), min.y(), min.z()), -dx, dy);
+        side(Point3::new(min.x(), min.y(), min.z()), dz, dy);
+        side(Point3::new(min.x(), max.y(), max.z()), dx, -dz);
+        side(Point3::new(min.x(), min.y(), min.z()), dx, dz);
+
+        Arc::new(Self {
+            sides,
+            bbox: bbox.padded(),
+        })
+    }
+}
+
+impl Hittable for AxisBox {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return None;
+        }
+        self.sides
+            .iter()
+            .filter_map(|side| side.surface_hit(ray, t_min, t_max))
+            .min_by(|a, b| a.record.t.total_cmp(&b.record.t))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let hits = self
+            .sides
+            .iter()
+            .filter_map(|side| side.surface_hit(ray, f64::NEG_INFINITY, f64::INFINITY))
+            .collect();
+        Some(Span::convex(hits).into_iter().collect())
+    }
+}
+
+// the ray parameter of where the ray crosses the plane through point
+fn plane_intersection(
+    point: Point3,
+    normal: Vec3,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<f64> {
+    let denominator = normal.dot(ray.direction);
+    // the ray is parallel to the plane
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let t = normal.dot(point - ray.origin) / denominator;
+    (t_min..=t_max).contains(&t).then_some(t)
+}
+
+impl Light for Quad {
+    fn sample(&self, origin: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let (a, b) = sampler.get_2d();
+        let point = self.q + a * self.u + b * self.v;
+        if (point - origin).is_near_zero() {
+            return None;
+        }
+        let direction = (point - origin).as_unit();
+        let pdf = self.pdf(origin, direction);
+        (pdf > 0.0).then_some(LightSample { direction, pdf })
+    }
+
+    fn pdf(&self, origin: Point3, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction, 0.0);
+        let Some((t, _, _)) = self.intersect(&ray, 0.0, f64::INFINITY) else {
+            return 0.0;
+        };
+        // convert the uniform density over the area into a density over solid angle
+        let distance_sq = t * t * direction.length_sq();
+        let cosine = (direction.dot(self.normal) / direction.length()).abs();
+        if cosine < 1e-12 {
+            return 0.0;
+        }
+        distance_sq / (cosine * self.area)
+    }
+}
@@ -5,11 +5,17 @@ pub struct Ray {
     pub origin: Point3,
     // ray direction need not be normal
     pub direction: Vec3,
+    // the instant the ray is traced at, for moving objects
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+    pub fn new(origin: Point3, direction: Vec3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+        }
     }
 
     pub fn at(&self, t: f64) -> Point3 {
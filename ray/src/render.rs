@@ -1,15 +1,25 @@
-use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
 
 use crate::bitmap::Bitmap;
+use crate::checkpoint::*;
+use crate::environment::*;
 use crate::hittable::*;
+use crate::light::{Light, LightSample};
 use crate::material::*;
+use crate::medium::Atmosphere;
 use crate::ray::Ray;
+use crate::sampler::*;
 use crate::vec::*;
-use rand::random;
 
 pub struct Raytracer {
     config: Config,
-    world: Rc<dyn Hittable>,
+    world: Arc<dyn Hittable>,
+    // emitters to sample directly, which must also be part of the world, and the environment if
+    // it can be sampled
+    lights: Vec<Arc<dyn Light>>,
 }
 
 pub struct Config {
@@ -22,9 +32,77 @@ pub struct Config {
     // camera - focus
     pub focus_dist: f64,
     pub aperture: f64,
+    // camera - the interval the shutter is open for, over which rays are spread for motion blur
+    pub shutter_open: f64,
+    pub shutter_close: f64,
     // renderer config
     pub samples_per_pixel: u32,
+    // takes as many samples as each pixel needs instead of samples_per_pixel, if set
+    pub adaptive: Option<AdaptiveSampling>,
     pub bounce_limit: u32,
+    // picks the random numbers of the render, the same seed giving the same image
+    pub seed: u64,
+    // how the random numbers are spread over the samples of a pixel
+    pub sampler: SamplerKind,
+    // skip gamma correction, leaving the bitmap in linear radiance (for HDR output)
+    pub linear_output: bool,
+    // light arriving from rays escaping the scene
+    pub environment: Arc<dyn Environment>,
+    // fog filling the scene, if any
+    pub atmosphere: Option<Atmosphere>,
+    // number of worker threads, 0 uses all available cores
+    pub threads: usize,
+}
+
+// Each pixel takes samples until the estimated error of its displayed brightness falls below
+// noise_threshold, but no fewer than min_samples and no more than max_samples, so flat parts of the
+// image that converge early leave more time for the noisy ones.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    // the standard error left of the gamma corrected luminance, from 0 to 1
+    pub noise_threshold: f64,
+}
+
+// When a progressive render stops: once every pixel has target_samples samples (by default
+// samples_per_pixel, or the most adaptive sampling takes), or once the time budget runs out,
+// whichever comes first.
+#[derive(Clone, Debug, Default)]
+pub struct Progression {
+    pub target_samples: Option<u32>,
+    pub time_budget: Option<Duration>,
+    // saves the progress of the render every so often, to resume it from if it is stopped
+    pub checkpointing: Option<Checkpointing>,
+}
+
+// The image after a pass of a progressive render
+pub struct Snapshot {
+    pub bitmap: Bitmap,
+    // the number of samples of each pixel, as from render_with_heatmap
+    pub heatmap: Bitmap,
+    // the samples per pixel the passes so far went up to, which the last pass may not have
+    // reached everywhere if time ran out
+    pub samples: u32,
+    pub elapsed: Duration,
+}
+
+// the samples a pixel has taken so far
+#[derive(Clone, Default)]
+pub(crate) struct Pixel {
+    pub sum: Color,
+    pub stats: PixelStats,
+    // the index of the next sample, past those skipped once the pixel converged
+    pub next: u32,
+}
+
+// running mean and variance of the luminance of the samples of a pixel (Welford's algorithm)
+#[derive(Clone, Default)]
+pub(crate) struct PixelStats {
+    pub count: u32,
+    pub mean: f64,
+    // the sum of squared differences from the mean
+    pub m2: f64,
 }
 
 pub struct Camera {
@@ -35,6 +113,18 @@ pub struct Camera {
     lens_raidus: f64,
     u: Vec3,
     v: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            max_samples: 1024,
+            noise_threshold: 0.01,
+        }
+    }
 }
 
 impl Default for Config {
@@ -46,88 +136,491 @@ impl Default for Config {
             vertical_fov: 120.0f64.to_radians(),
             viewport_scale: 2.0,
             samples_per_pixel: 100,
+            adaptive: None,
             bounce_limit: 50,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            linear_output: false,
+            environment: Gradient::sky(),
+            atmosphere: None,
             focus_dist: 1.0,
             aperture: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            threads: 0,
+        }
+    }
+}
+
+impl Config {
+    // A hash of the settings that change the image. The environment and atmosphere are left to
+    // the fingerprint of the scene they come from, and the number of threads doesn't matter.
+    pub fn fingerprint(&self) -> u64 {
+        let vectors = [self.lookfrom, self.lookto, self.vup];
+        let mut values: Vec<f64> = vectors.iter().flat_map(|v| [v.x(), v.y(), v.z()]).collect();
+        values.extend([
+            self.vertical_fov,
+            self.viewport_scale,
+            self.focus_dist,
+            self.aperture,
+            self.shutter_open,
+            self.shutter_close,
+        ]);
+        let adaptive = self.adaptive.map(|adaptive| {
+            (
+                adaptive.min_samples,
+                adaptive.max_samples,
+                adaptive.noise_threshold,
+            )
+        });
+        let (min_samples, max_samples, noise_threshold) = adaptive.unwrap_or_default();
+        values.push(noise_threshold);
+
+        let mut bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        for value in [
+            self.samples_per_pixel,
+            self.adaptive.is_some() as u32,
+            min_samples,
+            max_samples,
+            self.bounce_limit,
+            self.sampler as u32,
+            self.linear_output as u32,
+        ] {
+            bytes.extend(value.to_le_bytes());
         }
+        bytes.extend(self.seed.to_le_bytes());
+        fingerprint(&bytes)
     }
 }
 
 impl Raytracer {
-    pub fn new(config: Config, world: Rc<dyn Hittable>) -> Self {
-        Self { world, config }
+    pub fn new(config: Config, world: Arc<dyn Hittable>) -> Self {
+        let mut lights: Vec<Arc<dyn Light>> = vec![];
+        if config.environment.can_sample() {
+            lights.push(Arc::new(EnvironmentLight(Arc::clone(&config.environment))));
+        }
+        Self {
+            world,
+            config,
+            lights,
+        }
+    }
+
+    pub fn with_lights(mut self, lights: Vec<Arc<dyn Light>>) -> Self {
+        self.lights.extend(lights);
+        self
     }
 
     pub fn render(self, image_size: Size) -> Bitmap {
+        self.render_with_heatmap(image_size).0
+    }
+
+    // Renders the image along with a heat map of the number of samples each pixel took, from
+    // black through red and yellow to white at the most a pixel can take.
+    pub fn render_with_heatmap(self, image_size: Size) -> (Bitmap, Bitmap) {
         let camera = Camera::new(image_size, &self.config);
-        let mut bitmap = Bitmap::new(image_size);
-        let render_start = std::time::Instant::now();
-
-        for j in 0..bitmap.height() {
-            eprint!(
-                "{}{}/{} scanlines done. ({}%) [{:.1?} elapsed]",
-                clear_line(),
-                j,
-                bitmap.height() - 1,
-                100 * j / (bitmap.height() - 1),
-                render_start.elapsed(),
+        let max_samples = self.sample_bounds().1;
+        let mut pixels = vec![Pixel::default(); image_size.area() as usize];
+        self.render_pass(
+            &camera,
+            image_size,
+            &mut pixels,
+            max_samples,
+            max_samples,
+            None,
+        );
+        self.images(image_size, &pixels, max_samples)
+    }
+
+    // Renders the whole image in passes, each taking as many samples of every pixel as all the
+    // passes before it, and hands a snapshot of the image to on_snapshot after every pass. The
+    // images are the same as those of a render taking all the samples at once, even when resumed
+    // from a checkpoint. Fails if the checkpoint to resume from doesn't match the render.
+    pub fn render_progressive(
+        self,
+        image_size: Size,
+        progression: Progression,
+        mut on_snapshot: impl FnMut(&Snapshot),
+    ) -> Result<Snapshot> {
+        let camera = Camera::new(image_size, &self.config);
+        let target_samples = progression
+            .target_samples
+            .unwrap_or(self.sample_bounds().1)
+            .max(1);
+        let render_start = Instant::now();
+        let deadline = progression.time_budget.map(|budget| render_start + budget);
+        let checkpointing = progression.checkpointing.as_ref();
+
+        let mut checkpoint = Checkpoint {
+            size: image_size,
+            scene: checkpointing.map_or(0, |checkpointing| checkpointing.scene),
+            config: self.config.fingerprint(),
+            target_samples,
+            samples: 1,
+            pixels: vec![Pixel::default(); image_size.area() as usize],
+        };
+        if let Some(checkpointing) = checkpointing.filter(|c| c.resume && c.path.exists()) {
+            let saved = checkpoint.load_resumable(&checkpointing.path)?;
+            eprintln!(
+                "Resuming from {} at {} samples per pixel",
+                checkpointing.path.display(),
+                saved.samples
             );
-            for i in 0..bitmap.width() {
-                // perform anti-aliasing by randomized super-sampling
-                let color: Color = (0..self.config.samples_per_pixel)
-                    .into_iter()
-                    .map(|_| {
-                        let u = (i as f64 + random::<f64>()) / (bitmap.width() as f64 - 1.0);
-                        let v = (j as f64 + random::<f64>()) / (bitmap.height() as f64 - 1.0);
-
-                        let ray = camera.ray_at(u, v);
-                        self.project(&ray, self.config.bounce_limit)
-                    })
-                    .sum();
-                bitmap.set(i, j, self.emit_color(color))
+            checkpoint = saved;
+        }
+        let mut next_checkpoint = checkpointing.map(|c| Instant::now() + c.interval);
+
+        loop {
+            let samples = checkpoint.samples;
+            // The time budget waits for the first pass to finish, so that every pixel has a color,
+            // though checkpoints may still break it up.
+            let pass_deadline = [deadline.filter(|_| samples > 1), next_checkpoint]
+                .into_iter()
+                .flatten()
+                .min();
+            let pixels = &mut checkpoint.pixels;
+            self.render_pass(
+                &camera,
+                image_size,
+                pixels,
+                samples,
+                target_samples,
+                pass_deadline,
+            );
+            let complete = pixels.iter().all(|pixel| pixel.next >= samples);
+            let out_of_time = (samples > 1 || complete)
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            let done = (complete && samples == target_samples) || out_of_time;
+
+            if let Some(checkpointing) = checkpointing {
+                if done || next_checkpoint.is_some_and(|next| Instant::now() >= next) {
+                    // a render is better off going on without checkpoints than stopping
+                    if let Err(error) = checkpoint.save(&checkpointing.path) {
+                        eprintln!("failed to write checkpoint: {error:#}");
+                    }
+                    next_checkpoint = Some(Instant::now() + checkpointing.interval);
+                }
+            }
+            if !complete && !done {
+                continue;
+            }
+
+            let (bitmap, heatmap) = self.images(image_size, &checkpoint.pixels, target_samples);
+            let snapshot = Snapshot {
+                bitmap,
+                heatmap,
+                samples,
+                elapsed: render_start.elapsed(),
+            };
+            on_snapshot(&snapshot);
+            if done {
+                return Ok(snapshot);
+            }
+            checkpoint.samples = (2 * samples).min(target_samples);
+        }
+    }
+
+    // the least and most samples a pixel takes
+    fn sample_bounds(&self) -> (u32, u32) {
+        match self.config.adaptive {
+            Some(adaptive) => (adaptive.min_samples, adaptive.max_samples),
+            None => (self.config.samples_per_pixel, self.config.samples_per_pixel),
+        }
+    }
+
+    // Takes the samples of every pixel up to the given number, unless it has already converged,
+    // on the threads of the config, out of sample_count for the whole render. Once past the
+    // deadline, the scanlines not yet started are left as they are.
+    fn render_pass(
+        &self,
+        camera: &Camera,
+        image_size: Size,
+        pixels: &mut [Pixel],
+        samples: u32,
+        sample_count: u32,
+        deadline: Option<Instant>,
+    ) {
+        let render_start = Instant::now();
+        let threads = match self.config.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        // scanlines are handed out one at a time to whichever worker is free
+        let scanlines = Mutex::new(pixels.chunks_mut(image_size.width as usize).enumerate());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let scanlines = &scanlines;
+                scope.spawn(move || loop {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        break;
+                    }
+                    let Some((j, scanline)) = scanlines.lock().unwrap().next() else {
+                        break;
+                    };
+                    let mut sampler = self.config.sampler.create(self.config.seed, sample_count);
+                    self.render_scanline(
+                        camera,
+                        image_size,
+                        j as u32,
+                        scanline,
+                        samples,
+                        sampler.as_mut(),
+                    );
+                    if sender.send(j).is_err() {
+                        break;
+                    }
+                });
+            }
+            // drop our own sender so the receiver finishes once all workers are done
+            drop(sender);
+
+            for (done, _) in receiver.iter().enumerate() {
+                eprint!(
+                    "{}{}/{} scanlines done, {} spp. ({}%) [{:.1?} elapsed, {} threads]",
+                    clear_line(),
+                    done + 1,
+                    image_size.height,
+                    samples,
+                    100 * (done + 1) / image_size.height as usize,
+                    render_start.elapsed(),
+                    threads,
+                );
             }
+        });
+        eprintln!();
+    }
+
+    // takes the samples of the pixels of a scanline up to the given number
+    fn render_scanline(
+        &self,
+        camera: &Camera,
+        image_size: Size,
+        j: u32,
+        scanline: &mut [Pixel],
+        samples: u32,
+        sampler: &mut dyn Sampler,
+    ) {
+        for (i, pixel) in (0..).zip(scanline) {
+            // perform anti-aliasing by randomized super-sampling
+            for index in pixel.next..samples {
+                if self.converged(&pixel.stats) {
+                    break;
+                }
+                sampler.start_sample(i, j, index);
+                let (du, dv) = sampler.get_2d();
+                let u = (i as f64 + du) / (image_size.width as f64 - 1.0);
+                let v = (j as f64 + dv) / (image_size.height as f64 - 1.0);
+
+                let ray = camera.ray_at(u, v, sampler);
+                let sample = self.project(ray, self.config.bounce_limit, sampler);
+                pixel.sum += sample;
+                pixel.stats.add(luminance(sample));
+            }
+            pixel.next = pixel.next.max(samples);
         }
-        eprint!("\n");
+    }
 
-        bitmap
+    // whether adaptive sampling is done with the pixel
+    fn converged(&self, stats: &PixelStats) -> bool {
+        self.config.adaptive.is_some_and(|adaptive| {
+            stats.count >= adaptive.min_samples && stats.error() < adaptive.noise_threshold
+        })
     }
 
-    fn emit_color(&self, mut color: Color) -> Color {
-        let scale = 1.0 / self.config.samples_per_pixel as f64;
+    // the image of the pixels and their heat map, up to max_samples
+    fn images(&self, image_size: Size, pixels: &[Pixel], max_samples: u32) -> (Bitmap, Bitmap) {
+        let mut bitmap = Bitmap::new(image_size);
+        let mut heatmap = Bitmap::new(image_size);
+        let coordinates =
+            (0..image_size.height).flat_map(|j| (0..image_size.width).map(move |i| (i, j)));
+        for ((i, j), pixel) in coordinates.zip(pixels) {
+            let samples = pixel.stats.count;
+            bitmap.set(i, j, self.emit_color(pixel.sum, samples));
+            heatmap.set(i, j, heat(samples as f64 / max_samples as f64));
+        }
+        (bitmap, heatmap)
+    }
+
+    fn emit_color(&self, mut color: Color, samples: u32) -> Color {
+        if samples == 0 {
+            return Color::ZERO;
+        }
+        let scale = 1.0 / samples as f64;
         // normalize after super-sampling
         color *= scale;
+        if self.config.linear_output {
+            return color;
+        }
         // gamma correction
         color.sqrt()
     }
 
-    fn project(&self, ray: &Ray, bounce_limit: u32) -> Color {
-        if bounce_limit <= 0 {
-            return Color::ZERO;
-        }
+    // Traces a path from the camera ray, adding up the light reaching it at every bounce. Light is
+    // gathered both by sampling the lights directly and by scattered rays happening to hit an
+    // emitter, with the two estimates combined through multiple importance sampling. Scattering in
+    // the atmosphere is handled like bouncing off a surface, with its phase function as material.
+    fn project(&self, mut ray: Ray, bounce_limit: u32, sampler: &mut dyn Sampler) -> Color {
+        let mut radiance = Color::ZERO;
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        // density of the scattering that produced the ray, None if it can't be weighed against
+        // light sampling (camera rays and specular bounces)
+        let mut scatter_pdf = None;
 
-        if let Some(hit) = self.world.hit(ray, 0.001, std::f64::INFINITY) {
-            // assume a matte surface: diffuse to a random direction
-            return match hit.material.scatter(ray, &hit.record) {
-                ScatterResult::Scattered {
-                    scattered,
-                    attenuation,
-                } => {
-                    let color = self.project(&scattered, bounce_limit - 1);
-                    return attenuation * color;
+        for _ in 0..bounce_limit {
+            let mut hit = self.world.hit(&ray, 0.001, f64::INFINITY, sampler);
+            if let Some(atmosphere) = &self.config.atmosphere {
+                let t_max = hit.as_ref().map_or(f64::INFINITY, |hit| hit.record.t);
+                if let Some(scattered) = atmosphere.scatter(&ray, t_max, sampler) {
+                    hit = Some(scattered);
                 }
-                ScatterResult::Absorbed => Color::ZERO,
+            }
+            let Some(hit) = hit else {
+                radiance +=
+                    throughput * self.bg_color(&ray) * self.scatter_weight(&ray, scatter_pdf);
+                break;
             };
+
+            let emitted = hit.material.emitted(&ray, &hit.record);
+            if !emitted.is_near_zero() {
+                radiance += throughput * emitted * self.scatter_weight(&ray, scatter_pdf);
+            }
+
+            let ScatterResult::Scattered {
+                scattered,
+                attenuation,
+            } = hit.material.scatter(&ray, &hit.record, sampler)
+            else {
+                break;
+            };
+
+            let pdf = hit.material.pdf(&ray, &hit.record, scattered.direction);
+            scatter_pdf = (pdf > 0.0 && !self.lights.is_empty()).then_some(pdf);
+            if scatter_pdf.is_some() {
+                radiance += throughput * self.sample_light(&ray, &hit, sampler);
+            }
+
+            throughput *= attenuation;
+            ray = scattered;
+        }
+        radiance
+    }
+
+    // Estimates the light arriving directly at the hit from a randomly picked light.
+    fn sample_light(&self, ray: &Ray, hit: &HitResult, sampler: &mut dyn Sampler) -> Color {
+        let count = self.lights.len();
+        let light = &self.lights[((sampler.get_1d() * count as f64) as usize).min(count - 1)];
+        let Some(sample) = light.sample(hit.record.point, sampler) else {
+            return Color::ZERO;
+        };
+
+        let bsdf = hit.material.eval(ray, &hit.record, sample.direction);
+        if bsdf.is_near_zero() {
+            return Color::ZERO;
+        }
+        let shadow_ray = Ray::new(hit.record.point, sample.direction, ray.time);
+        let incoming = self.incoming_light(&shadow_ray, sampler);
+
+        let light_pdf = self.light_pdf(&shadow_ray);
+        let scatter_pdf = hit.material.pdf(ray, &hit.record, sample.direction);
+        bsdf * incoming * power_heuristic(light_pdf, scatter_pdf) / light_pdf
+    }
+
+    // the light emitted towards the origin of the ray by the first surface it reaches, dimmed by
+    // the media and atmosphere in between
+    fn incoming_light(&self, ray: &Ray, sampler: &mut dyn Sampler) -> Color {
+        let (light, t) = match self.world.shadow_hit(ray, 0.001, f64::INFINITY, sampler) {
+            Some(hit) => (hit.material.emitted(ray, &hit.record), hit.record.t),
+            None => (self.bg_color(ray), f64::INFINITY),
+        };
+        if light.is_near_zero() {
+            return Color::ZERO;
+        }
+        let mut transmittance = self.world.transmittance(ray, 0.001, t, sampler);
+        if let Some(atmosphere) = &self.config.atmosphere {
+            transmittance *= atmosphere.transmittance(ray, t);
+        }
+        transmittance * light
+    }
+
+    // the density of picking the ray's direction by sampling a random light
+    fn light_pdf(&self, ray: &Ray) -> f64 {
+        let total: f64 = self
+            .lights
+            .iter()
+            .map(|light| light.pdf(ray.origin, ray.direction))
+            .sum();
+        total / self.lights.len() as f64
+    }
+
+    // MIS weight for light found by a scattered ray, against having sampled the lights instead
+    fn scatter_weight(&self, ray: &Ray, scatter_pdf: Option<f64>) -> f64 {
+        match scatter_pdf {
+            Some(pdf) => power_heuristic(pdf, self.light_pdf(ray)),
+            None => 1.0,
         }
-        self.bg_color(ray)
     }
 
     fn bg_color(&self, ray: &Ray) -> Color {
-        let unit_direction = (ray.direction).as_unit();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        Color::new(1.0, 1.0, 1.0).lerp(t, Color::new(0.5, 0.7, 1.0))
+        self.config.environment.radiance(ray.direction)
+    }
+}
+
+// The environment as a light infinitely far away, which looks the same from every origin
+struct EnvironmentLight(Arc<dyn Environment>);
+
+impl Light for EnvironmentLight {
+    fn sample(&self, _: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        self.0.sample(sampler)
+    }
+
+    fn pdf(&self, _: Point3, direction: Vec3) -> f64 {
+        self.0.pdf(direction)
+    }
+}
+
+impl PixelStats {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // the estimated standard error of the gamma corrected luminance of the pixel
+    fn error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        let standard_error = (variance / self.count as f64).sqrt();
+        // scaled by the slope of the gamma curve (a square root) at the mean
+        standard_error / (2.0 * self.mean.max(1e-6).sqrt())
     }
 }
 
+// a color for a share from 0 to 1, going from black through red and yellow to white
+fn heat(share: f64) -> Color {
+    let t = 3.0 * share.clamp(0.0, 1.0);
+    Color::new(
+        t.min(1.0),
+        (t - 1.0).clamp(0.0, 1.0),
+        (t - 2.0).clamp(0.0, 1.0),
+    )
+}
+
+// Veach's power heuristic (with an exponent of 2), weighing a sample taken with density pdf against
+// another strategy with density other_pdf
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
 fn clear_line() -> &'static str {
     "\x1B[2K\r"
 }
@@ -139,9 +632,8 @@ impl Camera {
         let viewport_width = image_size.aspect_ratio() * viewport_height;
 
         let origin = config.lookfrom;
-        assert!(config.vup.length_sq() == 1.0);
         let w = (config.lookfrom - config.lookto).as_unit();
-        let u = config.vup.cross(w).as_unit();
+        let u = config.vup.as_unit().cross(w).as_unit();
         let v = w.cross(u);
         let horizontal = u * config.focus_dist * viewport_width;
         let vertical = v * config.focus_dist * viewport_height;
@@ -155,15 +647,68 @@ impl Camera {
             lens_raidus: config.aperture / 2.0,
             u,
             v,
+            shutter_open: config.shutter_open,
+            shutter_close: config.shutter_close,
         }
     }
 
-    fn ray_at(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_raidus * Vec3::random_in_unit_disk();
+    fn ray_at(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_raidus * Vec3::random_in_unit_disk(sampler);
         let offset = self.u * rd.x() + self.v * rd.y();
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+            self.shutter_open + sampler.get_1d() * (self.shutter_close - self.shutter_open),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(seed: u64, threads: usize, sampler: SamplerKind) -> Bitmap {
+        let mut world = World::new();
+        let ground = ApproxLambertian::new(Color::new(0.5, 0.5, 0.5));
+        world.add(Sphere::new(Point3::new(0, -100.5, -1), 100.0, ground));
+        world.add(Sphere::new(
+            Point3::new(0, 0, -1),
+            0.5,
+            Dielectric::new(1.5),
+        ));
+        let config = Config {
+            samples_per_pixel: 4,
+            bounce_limit: 8,
+            seed,
+            sampler,
+            threads,
+            ..Default::default()
+        };
+        Raytracer::new(config, Arc::new(world)).render(Size::new(8, 6))
+    }
+
+    fn bits(bitmap: &Bitmap) -> Vec<u64> {
+        let (width, height) = (bitmap.width(), bitmap.height());
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let color = bitmap.get(x, y);
+                [color.x(), color.y(), color.z()].map(f64::to_bits)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_image_on_any_number_of_threads() {
+        for sampler in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let image = bits(&render(7, 1, sampler));
+            assert_eq!(image, bits(&render(7, 4, sampler)), "{sampler:?}");
+            assert_ne!(image, bits(&render(8, 1, sampler)), "{sampler:?}");
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+// The source of the random numbers of a render, handed out as the dimensions of a sample: the
+// first ones place the sample in the pixel and on the lens, the rest pick the path's directions.
+// Samples are worked out from the seed of the render, the pixel and the index of the sample alone,
+// so that the image doesn't depend on the order in which samples are taken or on the thread
+// taking them. Low-discrepancy samplers spread the samples of each dimension over a pixel more
+// evenly than independent random numbers, for less noise at the same number of samples.
+pub trait Sampler: Send {
+    // starts the index-th sample of the pixel (i, j), from its first dimension
+    fn start_sample(&mut self, i: u32, j: u32, index: u32);
+
+    // the next dimension of the sample, in [0, 1)
+    fn get_1d(&mut self) -> f64;
+
+    // the next two dimensions of the sample, which are spread out together
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum SamplerKind {
+    // independent uniform random numbers
+    #[default]
+    Independent,
+    // jittered: a random point in each of as many strata as there are samples per pixel, the
+    // strata of each dimension shuffled separately
+    Stratified,
+    // the Halton sequence, with Owen scrambling per pixel
+    Halton,
+    // the first two dimensions of the Sobol sequence, shuffled and Owen scrambled anew for each
+    // dimension of the sample
+    Sobol,
+}
+
+impl SamplerKind {
+    // a sampler for renders taking samples_per_pixel samples of each pixel, which the stratified
+    // sampler divides its dimensions by
+    pub fn create(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        let start = Start::default();
+        match self {
+            SamplerKind::Independent => Box::new(Independent {
+                seed,
+                rng: start.rng(seed),
+            }),
+            SamplerKind::Stratified => {
+                let count = samples_per_pixel.max(1);
+                // the most even grid of count cells, for two dimensions
+                let columns = (1..=count)
+                    .take_while(|n| n * n <= count)
+                    .filter(|n| count.is_multiple_of(*n))
+                    .last()
+                    .unwrap_or(1);
+                Box::new(Stratified {
+                    seed,
+                    count,
+                    grid: (columns, count / columns),
+                    start,
+                    dimension: 0,
+                    rng: start.rng(seed),
+                })
+            }
+            SamplerKind::Halton => Box::new(Halton {
+                seed,
+                start,
+                dimension: 0,
+                rng: start.rng(seed),
+            }),
+            SamplerKind::Sobol => Box::new(Sobol {
+                seed,
+                start,
+                dimension: 0,
+            }),
+        }
+    }
+}
+
+// the pixel and index of the sample being taken
+#[derive(Clone, Copy, Default)]
+struct Start {
+    pixel: u64,
+    index: u32,
+}
+
+impl Start {
+    fn new(i: u32, j: u32, index: u32) -> Self {
+        Self {
+            pixel: (j as u64) << 32 | i as u64,
+            index,
+        }
+    }
+
+    // random numbers of the sample alone, each pixel drawing from its own stream
+    fn rng(self, seed: u64) -> Pcg32 {
+        Pcg32::new(hash(&[seed, self.index as u64]), self.pixel)
+    }
+
+    // a hash of the dimension of the sample, the same for all samples of the pixel
+    fn hash(self, seed: u64, dimension: u32) -> u64 {
+        hash(&[seed, self.pixel, dimension as u64])
+    }
+}
+
+struct Independent {
+    seed: u64,
+    rng: Pcg32,
+}
+
+struct Stratified {
+    seed: u64,
+    // the number of strata of each dimension
+    count: u32,
+    // the columns and rows of strata, for pairs of dimensions
+    grid: (u32, u32),
+    start: Start,
+    dimension: u32,
+    // for jittering within strata, and for samples past the count
+    rng: Pcg32,
+}
+
+struct Halton {
+    seed: u64,
+    start: Start,
+    dimension: u32,
+    // for dimensions past the bases in PRIMES
+    rng: Pcg32,
+}
+
+struct Sobol {
+    seed: u64,
+    start: Start,
+    dimension: u32,
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.rng = Start::new(i, j, index).rng(self.seed);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.start = Start::new(i, j, index);
+        self.dimension = 0;
+        self.rng = self.start.rng(self.seed);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.start.hash(self.seed, self.dimension);
+        self.dimension += 1;
+        if self.start.index >= self.count {
+            return self.rng.gen();
+        }
+        let stratum = permutation_element(self.start.index, self.count, hash as u32);
+        (stratum as f64 + self.rng.gen::<f64>()) / self.count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.start.hash(self.seed, self.dimension);
+        self.dimension += 2;
+        if self.start.index >= self.count {
+            return (self.rng.gen(), self.rng.gen());
+        }
+        let stratum = permutation_element(self.start.index, self.count, hash as u32);
+        let (columns, rows) = self.grid;
+        (
+            ((stratum % columns) as f64 + self.rng.gen::<f64>()) / columns as f64,
+            ((stratum / columns) as f64 + self.rng.gen::<f64>()) / rows as f64,
+        )
+    }
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.start = Start::new(i, j, index);
+        self.dimension = 0;
+        self.rng = self.start.rng(self.seed);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let Some(&base) = PRIMES.get(self.dimension as usize) else {
+            return self.rng.gen();
+        };
+        let hash = self.start.hash(self.seed, self.dimension);
+        self.dimension += 1;
+        scrambled_radical_inverse(base, self.start.index as u64, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, i: u32, j: u32, index: u32) {
+        self.start = Start::new(i, j, index);
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.start.hash(self.seed, self.dimension);
+        self.dimension += 1;
+        let index = nested_uniform_scramble(self.start.index, hash as u32);
+        to_unit(nested_uniform_scramble(
+            sobol(index, 0),
+            (hash >> 32) as u32,
+        ))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.start.hash(self.seed, self.dimension);
+        self.dimension += 2;
+        let index = nested_uniform_scramble(self.start.index, hash as u32);
+        let second = mix_bits(hash) as u32;
+        (
+            to_unit(nested_uniform_scramble(
+                sobol(index, 0),
+                (hash >> 32) as u32,
+            )),
+            to_unit(nested_uniform_scramble(sobol(index, 1), second)),
+        )
+    }
+}
+
+// the bases of the dimensions of the Halton sequence
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// The index-th point of a dimension of the Sobol sequence (0 or 1), as a fraction of 2^32. The
+// first dimension is the van der Corput sequence, and the second has direction numbers
+// v_k = v_(k-1) ^ (v_(k-1) >> 1).
+fn sobol(index: u32, dimension: u32) -> u32 {
+    let mut direction = 1 << 31;
+    let mut point = 0;
+    for bit in 0..32 {
+        if index >> bit & 1 == 1 {
+            point ^= direction;
+        }
+        direction = match dimension {
+            0 => direction >> 1,
+            _ => direction ^ direction >> 1,
+        };
+    }
+    point
+}
+
+// Owen scrambling of the bits of x from the most significant down, by Burley's hash-based
+// permutation ("Practical Hash-based Owen Scrambling", 2020). Scrambling the index of a sample
+// this way shuffles the samples without breaking up the power of two sized blocks the Sobol
+// sequence is well spread out in.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    // Laine and Karras' permutation, which only ever carries bits upwards
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+// The radical inverse of a in base, with each digit permuted by a hash of the digits below it,
+// which is Owen scrambling.
+fn scrambled_radical_inverse(base: u32, mut a: u64, hash: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = 1.0;
+    let mut reversed = 0u64;
+    // until the digits are too small to matter, including the zeros past the last digit of a
+    while 1.0 - (base - 1) as f64 * scale < 1.0 {
+        let digit = (a % base as u64) as u32;
+        let digit = permutation_element(digit, base, mix_bits(hash ^ reversed) as u32);
+        reversed = reversed * base as u64 + digit as u64;
+        scale *= inverse_base;
+        a /= base as u64;
+    }
+    (scale * reversed as f64).min(1.0 - f64::EPSILON / 2.0)
+}
+
+// The i-th element of a random permutation of 0..n picked by seed, without building the
+// permutation (Kensler, "Correlated Multi-Jittered Sampling", 2013).
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut mask = n.wrapping_sub(1);
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // every step is invertible on the bits under the mask, so retrying until the result is below
+    // n walks the cycles of a permutation
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < n {
+            return (i.wrapping_add(seed)) % n;
+        }
+    }
+}
+
+fn to_unit(x: u32) -> f64 {
+    x as f64 / (1u64 << 32) as f64
+}
+
+// a hash of a few values, mixing the bits of each in turn
+fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e37_79b9_7f4a_7c15, |hash, &value| mix_bits(hash ^ value))
+}
+
+// scrambles the bits of v, so that every bit of the result depends on all of them
+fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+    v ^= v >> 33;
+    v
+}
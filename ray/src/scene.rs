@@ -0,0 +1,1229 @@
+// Declarative scene descriptions.
+//
+// A scene is a TOML file with a camera, render settings, named materials and a list of objects:
+//
+//     [camera]
+//     lookfrom = [13, 2, 3]
+//     lookto = [0, 0, 0]
+//     vertical_fov = 20 # degrees
+//
+//     [render]
+//     samples_per_pixel = 100
+//     seed = 7 # picks the random numbers, the same seed giving the same image
+//     sampler = "sobol" # or "independent", "stratified" or "halton"
+//
+// Instead of taking samples_per_pixel samples everywhere, pixels can take samples until their
+// noise falls below a threshold (the standard error of their displayed brightness, from 0 to 1):
+//
+//     [render.adaptive]
+//     min_samples = 16
+//     max_samples = 1024
+//     noise_threshold = 0.01
+//
+//     [materials.ground]
+//     type = "lambertian"
+//     albedo = [0.5, 0.5, 0.5]
+//
+//     [[objects]]
+//     type = "sphere"
+//     centre = [0, -1000, 0]
+//     radius = 1000
+//     material = "ground"
+//
+// Colors of materials can also refer to textures, declared as e.g.
+//
+//     [textures.floor]
+//     type = "checker"
+//     size = 0.5
+//     even = [0.2, 0.3, 0.1]
+//     odd = "marble" # another texture
+//
+// The light from beyond the scene is set by an environment, one of
+//
+//     [environment]
+//     type = "constant" # or "gradient", with optional bottom and top colors
+//     color = [0, 0, 0]
+//
+//     [environment]
+//     type = "map" # an equirectangular HDR image
+//     path = "sky.hdr"
+//     rotation = 90 # degrees about the y axis
+//     intensity = 2
+//
+//     [environment]
+//     type = "sky" # a daylight sky with a sun
+//     sun_direction = [1, 2, 0.5]
+//     turbidity = 3 # 2 (clear) to 10 (hazy)
+//     ground_albedo = [0.3, 0.3, 0.3]
+//
+// Objects can be moved with a list of transforms, applied in order:
+//
+//     [[objects]]
+//     type = "mesh"
+//     path = "bunny.obj"
+//     transform = [
+//         { scale = 2 }, # or per axis, e.g. [1, 2, 1]
+//         { rotate = { axis = [0, 1, 0], angle = 45 } }, # degrees
+//         { translate = [0, 1, 0] },
+//     ]
+//
+// Closed solids can be combined with a `csg` object, e.g. a lens:
+//
+//     [[objects]]
+//     type = "csg"
+//     operation = "intersection" # or "union", or "difference" (left minus right)
+//     left = { type = "sphere", centre = [0, 0, -0.8], radius = 1, material = "glass" }
+//     right = { type = "sphere", centre = [0, 0, 0.8], radius = 1, material = "glass" }
+//
+// Smoke and the like are media filling a closed solid, whose material is a phase function:
+//
+//     [materials.smoke]
+//     type = "isotropic" # or "henyey_greenstein", with an anisotropy from -1 (back) to 1 (forward)
+//     albedo = [0.8, 0.8, 0.8]
+//
+//     [[objects]]
+//     type = "medium"
+//     boundary = { type = "box", min = [0, 0, 0], max = [1, 1, 1], material = "smoke" }
+//     density = 2 # chance of scattering per unit distance
+//     material = "smoke"
+//
+// Volumes of varying density are loaded from voxel grids, either dense (Mitsuba's .vol) or sparse
+// (.svol, see VoxelGrid::load), filling the bounds stored in the grid file:
+//
+//     [[objects]]
+//     type = "volume"
+//     path = "smoke.vol"
+//     density_scale = 10 # scales the values of the grid
+//     material = "smoke"
+//     emission = { path = "temperature.vol", temperature_scale = 3000, intensity = 2 } # optional
+//
+// Motion blur spreads the rays of each pixel over the time the shutter is open, set in the camera
+// by `shutter_open = 0` and `shutter_close = 1`. Spheres can move in a straight line,
+//
+//     [[objects]]
+//     type = "moving_sphere"
+//     start = [0, 1, 0]
+//     end = [0, 1.5, 0] # where the centre is at end_time
+//     start_time = 0
+//     end_time = 1
+//     radius = 0.5
+//     material = "red"
+//
+// and any object can be moved through keyframes, between which it turns along the shortest way:
+//
+//     [[objects]]
+//     type = "animated"
+//     object = { type = "box", min = [-0.5, 0, -0.5], max = [0.5, 1, 0.5], material = "red" }
+//     keyframes = [
+//         { time = 0, translate = [0, 0, 0] },
+//         { time = 1, translate = [1, 0, 0], rotate = { axis = [0, 1, 0], angle = 30 } },
+//     ]
+//
+// Objects stay still before their first keyframe (or start_time) and after their last one.
+//
+// Fog filling the whole scene (or up to a height) is set by
+//
+//     [atmosphere]
+//     density = 0.02
+//     albedo = [1, 1, 1]
+//     anisotropy = 0.5
+//     height = 1.5 # optional
+//
+// Meshes loaded from the same file share their geometry. Transformed spheres and disks aren't
+// sampled directly as lights.
+//
+// Every camera and render setting is optional and falls back to `Config::default()`. Relative
+// paths (e.g. of meshes) are resolved against the directory of the scene file.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use crate::aabb::Aabb;
+use crate::bitmap::Bitmap;
+use crate::checkpoint::Fingerprint;
+use crate::csg::*;
+use crate::environment::*;
+use crate::grid::VoxelGrid;
+use crate::hittable::*;
+use crate::instance::*;
+use crate::light::Light;
+use crate::material::*;
+use crate::medium::*;
+use crate::mesh::TriangleMesh;
+use crate::obj::load_obj;
+use crate::planar::*;
+use crate::render::{AdaptiveSampling, Config};
+use crate::sampler::SamplerKind;
+use crate::solid::*;
+use crate::texture::*;
+use crate::vec::*;
+use crate::volume::*;
+
+pub struct Scene {
+    pub world: World,
+    pub config: Config,
+    // the spheres, quads and disks with an emissive material, which can be sampled directly
+    pub lights: Vec<Arc<dyn Light>>,
+    // a hash of the description and of every file it loads, which checkpoints of its renders are
+    // checked against
+    pub fingerprint: u64,
+}
+
+type Triple = [f64; 3];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    render: RenderDesc,
+    environment: Option<EnvironmentDesc>,
+    atmosphere: Option<AtmosphereDesc>,
+    #[serde(default)]
+    textures: HashMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: Option<Triple>,
+    lookto: Option<Triple>,
+    vup: Option<Triple>,
+    // in degrees
+    vertical_fov: Option<f64>,
+    aperture: Option<f64>,
+    focus_dist: Option<f64>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RenderDesc {
+    samples_per_pixel: Option<u32>,
+    bounce_limit: Option<u32>,
+    seed: Option<u64>,
+    sampler: Option<SamplerDesc>,
+    adaptive: Option<AdaptiveDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdaptiveDesc {
+    min_samples: Option<u32>,
+    max_samples: Option<u32>,
+    noise_threshold: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SamplerDesc {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDesc {
+    Constant {
+        color: Triple,
+    },
+    Gradient {
+        #[serde(default = "default_bottom")]
+        bottom: Triple,
+        #[serde(default = "default_top")]
+        top: Triple,
+    },
+    Map {
+        path: PathBuf,
+        // in degrees
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    Sky {
+        sun_direction: Triple,
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "default_ground_albedo")]
+        ground_albedo: Triple,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AtmosphereDesc {
+    density: f64,
+    #[serde(default = "default_albedo")]
+    albedo: Triple,
+    #[serde(default)]
+    anisotropy: f64,
+    height: Option<f64>,
+}
+
+// either a constant color or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorDesc {
+    Constant(Triple),
+    Texture(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Checker {
+        size: f64,
+        even: ColorDesc,
+        odd: ColorDesc,
+    },
+    // a PNG or PPM image
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapDesc,
+    },
+    Noise {
+        scale: f64,
+        #[serde(default)]
+        pattern: PatternDesc,
+    },
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum WrapDesc {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum PatternDesc {
+    #[default]
+    Noise,
+    Turbulence,
+    Marble,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: ColorDesc,
+    },
+    Metal {
+        albedo: ColorDesc,
+        fuzz: f64,
+    },
+    Dielectric {
+        refraction_index: f64,
+    },
+    DiffuseLight {
+        color: Triple,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+        #[serde(default)]
+        one_sided: bool,
+    },
+    // phase functions, for media
+    Isotropic {
+        albedo: ColorDesc,
+    },
+    HenyeyGreenstein {
+        albedo: ColorDesc,
+        anisotropy: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        centre: Triple,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // a sphere whose centre moves in a straight line from start (at start_time) to end (at
+    // end_time)
+    MovingSphere {
+        start: Triple,
+        end: Triple,
+        #[serde(default)]
+        start_time: f64,
+        #[serde(default = "default_end_time")]
+        end_time: f64,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // a parallelogram with edges u and v from the corner q
+    Quad {
+        q: Triple,
+        u: Triple,
+        v: Triple,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // an infinite plane through point
+    Plane {
+        point: Triple,
+        normal: Triple,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    Disk {
+        centre: Triple,
+        normal: Triple,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // an axis-aligned box between two opposite corners
+    Box {
+        min: Triple,
+        max: Triple,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // capped, from base to base + axis
+    Cylinder {
+        base: Triple,
+        axis: Triple,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // from a base of the given radius to the apex at base + axis
+    Cone {
+        base: Triple,
+        axis: Triple,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // a ring around axis
+    Torus {
+        centre: Triple,
+        axis: Triple,
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // the coefficients [a, ..., j] of a x^2 + b y^2 + c z^2 + d xy + e xz + f yz + g x + h y +
+    // i z + j = 0, clipped to the box between the corners min and max, if given
+    Quadric {
+        coefficients: [f64; 10],
+        min: Option<Triple>,
+        max: Option<Triple>,
+        material: String,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // a boolean combination of two solids, given as nested objects
+    Csg {
+        operation: OperationDesc,
+        left: Box<ObjectDesc>,
+        right: Box<ObjectDesc>,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // a volume of constant density filling a closed solid, scattering with the given phase function
+    Medium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        material: String,
+    },
+    // a medium of varying density from a voxel grid file, which may glow from a temperature grid
+    Volume {
+        path: PathBuf,
+        #[serde(default = "default_scale")]
+        density_scale: f64,
+        material: String,
+        emission: Option<EmissionDesc>,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+    // another object moving through the keyframes given, in place of its transform
+    Animated {
+        object: Box<ObjectDesc>,
+        keyframes: Vec<KeyframeDesc>,
+    },
+    // an OBJ file, whose own materials take precedence over the given one
+    Mesh {
+        path: PathBuf,
+        material: Option<String>,
+        #[serde(default)]
+        transform: Vec<TransformDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmissionDesc {
+    // a temperature grid over the same bounds as the density
+    path: PathBuf,
+    #[serde(default = "default_scale")]
+    temperature_scale: f64,
+    #[serde(default = "default_intensity")]
+    intensity: f64,
+}
+
+// a pose of an animated object: scaled, then rotated and then translated
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    #[serde(default)]
+    translate: Triple,
+    rotate: Option<RotationDesc>,
+    scale: Option<ScaleDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum OperationDesc {
+    Union,
+    Intersection,
+    Difference,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate(Triple),
+    Scale(ScaleDesc),
+    Rotate(RotationDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDesc {
+    axis: Triple,
+    // in degrees
+    angle: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    PerAxis(Triple),
+}
+
+impl Scene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&source, base_dir).with_context(|| format!("in scene {}", path.display()))
+    }
+
+    // Parses a scene description, resolving relative paths against base_dir.
+    pub fn parse(source: &str, base_dir: &Path) -> Result<Self> {
+        let desc: SceneDesc = toml::from_str(source)?;
+        let mut files = Fingerprint::new(base_dir);
+        files.add(Path::new(""), source.as_bytes());
+
+        let mut textures = HashMap::new();
+        for name in desc.textures.keys() {
+            let stack = &mut vec![];
+            build_texture(
+                &desc.textures,
+                name,
+                base_dir,
+                &mut textures,
+                stack,
+                &mut files,
+            )?;
+        }
+        let materials: HashMap<&str, Arc<dyn Material>> = desc
+            .materials
+            .iter()
+            .map(|(name, material)| Ok((name.as_str(), material.build(&textures)?)))
+            .collect::<Result<_>>()?;
+
+        let mut builder = ObjectBuilder {
+            materials,
+            material_descs: &desc.materials,
+            base_dir,
+            meshes: HashMap::new(),
+            lights: vec![],
+            files,
+        };
+        let mut world = World::new();
+        for object in &desc.objects {
+            for hittable in builder.build(object, true)? {
+                world.add(hittable);
+            }
+        }
+        let lights = builder.lights;
+        let mut files = builder.files;
+        let config = desc.config(base_dir, &mut files)?;
+
+        Ok(Self {
+            world,
+            config,
+            lights,
+            fingerprint: files.value(),
+        })
+    }
+}
+
+// Builds objects from their descriptions, collecting the lights to sample directly
+struct ObjectBuilder<'a> {
+    materials: HashMap<&'a str, Arc<dyn Material>>,
+    material_descs: &'a HashMap<String, MaterialDesc>,
+    base_dir: &'a Path,
+    // meshes by file and material, shared between the objects using them
+    meshes: HashMap<(&'a Path, Option<&'a str>), Vec<Arc<TriangleMesh>>>,
+    lights: Vec<Arc<dyn Light>>,
+    // the files loaded, for the fingerprint of the scene
+    files: Fingerprint,
+}
+
+impl<'a> ObjectBuilder<'a> {
+    // loads the grid at path, relative to the scene, adding it to the files of the scene
+    fn load_grid(&mut self, path: &Path) -> Result<VoxelGrid> {
+        let path = self.base_dir.join(path);
+        self.files.add_file(&path)?;
+        VoxelGrid::load(path)
+    }
+
+    fn material(&self, name: &str) -> Result<Arc<dyn Material>> {
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown material `{name}`"))
+    }
+
+    fn is_emissive(&self, name: &str) -> bool {
+        matches!(
+            self.material_descs.get(name),
+            Some(MaterialDesc::DiffuseLight { .. })
+        )
+    }
+
+    // Builds the hittables of an object (several for meshes with several materials). Emissive
+    // objects are added to the lights if sample_lights is set, and they can be sampled.
+    fn build(
+        &mut self,
+        object: &'a ObjectDesc,
+        sample_lights: bool,
+    ) -> Result<Vec<Arc<dyn Hittable>>> {
+        let hittable: Arc<dyn Hittable> = match object {
+            ObjectDesc::Sphere {
+                centre,
+                radius,
+                material: name,
+                transform,
+            } => {
+                let sphere = Sphere::new(vec3(*centre), *radius, self.material(name)?);
+                if sample_lights && self.is_emissive(name) && transform.is_empty() {
+                    self.lights.push(sphere.clone());
+                }
+                place(sphere, transform)?
+            }
+            ObjectDesc::MovingSphere {
+                start,
+                end,
+                start_time,
+                end_time,
+                radius,
+                material: name,
+                transform,
+            } => {
+                if end_time < start_time {
+                    bail!("a moving sphere must end after it starts");
+                }
+                let sphere = MovingSphere::new(
+                    vec3(*start),
+                    vec3(*end),
+                    *start_time,
+                    *end_time,
+                    *radius,
+                    self.material(name)?,
+                );
+                place(sphere, transform)?
+            }
+            ObjectDesc::Quad {
+                q,
+                u,
+                v,
+                material: name,
+                transform,
+            } => {
+                // an affine transform of a parallelogram is still one
+                let transform = build_transform(transform)?;
+                let quad = Quad::new(
+                    transform.point(vec3(*q)),
+                    transform.vector(vec3(*u)),
+                    transform.vector(vec3(*v)),
+                    self.material(name)?,
+                );
+                if sample_lights && self.is_emissive(name) {
+                    self.lights.push(quad.clone());
+                }
+                quad
+            }
+            ObjectDesc::Plane {
+                point,
+                normal,
+                material: name,
+                transform,
+            } => {
+                let plane = Plane::new(vec3(*point), vec3(*normal), self.material(name)?);
+                place(plane, transform)?
+            }
+            ObjectDesc::Disk {
+                centre,
+                normal,
+                radius,
+                material: name,
+                transform,
+            } => {
+                let disk = Disk::new(vec3(*centre), vec3(*normal), *radius, self.material(name)?);
+                if sample_lights && self.is_emissive(name) && transform.is_empty() {
+                    self.lights.push(disk.clone());
+                }
+                place(disk, transform)?
+            }
+            ObjectDesc::Box {
+                min,
+                max,
+                material: name,
+                transform,
+            } => {
+                let axis_box = AxisBox::new(vec3(*min), vec3(*max), self.material(name)?);
+                place(axis_box, transform)?
+            }
+            ObjectDesc::Cylinder {
+                base,
+                axis,
+                radius,
+                material: name,
+                transform,
+            } => {
+                let cylinder =
+                    Cylinder::new(vec3(*base), vec3(*axis), *radius, self.material(name)?);
+                place(cylinder, transform)?
+            }
+            ObjectDesc::Cone {
+                base,
+                axis,
+                radius,
+                material: name,
+                transform,
+            } => {
+                let cone = Cone::new(vec3(*base), vec3(*axis), *radius, self.material(name)?);
+                place(cone, transform)?
+            }
+            ObjectDesc::Torus {
+                centre,
+                axis,
+                major_radius,
+                minor_radius,
+                material: name,
+                transform,
+            } => {
+                let torus = Torus::new(
+                    vec3(*centre),
+                    vec3(*axis),
+                    *major_radius,
+                    *minor_radius,
+                    self.material(name)?,
+                );
+                place(torus, transform)?
+            }
+            ObjectDesc::Quadric {
+                coefficients,
+                min,
+                max,
+                material: name,
+                transform,
+            } => {
+                let bounds = match (min, max) {
+                    (Some(min), Some(max)) => Some(Aabb::new(vec3(*min), vec3(*max))),
+                    (None, None) => None,
+                    _ => bail!("quadric bounds need both a min and a max corner"),
+                };
+                let quadric = Quadric::new(*coefficients, bounds, self.material(name)?);
+                place(quadric, transform)?
+            }
+            ObjectDesc::Csg {
+                operation,
+                left,
+                right,
+                transform,
+            } => {
+                let operation = match operation {
+                    OperationDesc::Union => Operation::Union,
+                    OperationDesc::Intersection => Operation::Intersection,
+                    OperationDesc::Difference => Operation::Difference,
+                };
+                let csg = Csg::new(operation, self.build_solid(left)?, self.build_solid(right)?);
+                place(csg, transform)?
+            }
+            ObjectDesc::Medium {
+                boundary,
+                density,
+                material: name,
+            } => {
+                if *density <= 0.0 {
+                    bail!("medium density must be positive");
+                }
+                ConstantMedium::new(self.build_solid(boundary)?, *density, self.material(name)?)
+            }
+            ObjectDesc::Volume {
+                path,
+                density_scale,
+                material: name,
+                emission,
+                transform,
+            } => {
+                let emission = match emission {
+                    Some(desc) => Some(Emission::new(
+                        self.load_grid(&desc.path)?,
+                        desc.temperature_scale,
+                        desc.intensity,
+                    )),
+                    None => None,
+                };
+                let volume = Volume::new(
+                    self.load_grid(path)?,
+                    *density_scale,
+                    self.material(name)?,
+                    emission,
+                );
+                place(volume, transform)?
+            }
+            ObjectDesc::Animated { object, keyframes } => {
+                if keyframes.is_empty() {
+                    bail!("animated objects need at least one keyframe");
+                }
+                let mut keyframes = keyframes
+                    .iter()
+                    .map(KeyframeDesc::build)
+                    .collect::<Result<Vec<_>>>()?;
+                keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+                for pair in keyframes.windows(2) {
+                    let (a, b) = (pair[0].scale, pair[1].scale);
+                    if (0..3).any(|axis| a.axis(axis) * b.axis(axis) < 0.0) {
+                        bail!(
+                            "the scale changes sign between the keyframes at times {} and {}, \
+                             passing through zero",
+                            pair[0].time,
+                            pair[1].time
+                        );
+                    }
+                }
+                return Ok(self
+                    .build(object, false)?
+                    .into_iter()
+                    .map(|hittable| {
+                        AnimatedInstance::new(hittable, keyframes.clone()) as Arc<dyn Hittable>
+                    })
+                    .collect());
+            }
+            ObjectDesc::Mesh {
+                path,
+                material: name,
+                transform,
+            } => {
+                let key = (path.as_path(), name.as_deref());
+                if !self.meshes.contains_key(&key) {
+                    let default_material = match name {
+                        Some(name) => self.material(name)?,
+                        None => ApproxLambertian::new(Color::new(0.8, 0.8, 0.8)),
+                    };
+                    let meshes =
+                        load_obj(self.base_dir.join(path), default_material, &mut self.files)?;
+                    self.meshes.insert(key, meshes);
+                }
+                return self.meshes[&key]
+                    .iter()
+                    .map(|mesh| place(mesh.clone(), transform))
+                    .collect();
+            }
+        };
+        Ok(vec![hittable])
+    }
+
+    // builds an operand of a CSG object or the boundary of a medium, which must be a closed solid
+    fn build_solid(&mut self, object: &'a ObjectDesc) -> Result<Arc<dyn Hittable>> {
+        match object {
+            ObjectDesc::Sphere { .. }
+            | ObjectDesc::MovingSphere { .. }
+            | ObjectDesc::Box { .. }
+            | ObjectDesc::Cylinder { .. }
+            | ObjectDesc::Cone { .. }
+            | ObjectDesc::Torus { .. }
+            | ObjectDesc::Csg { .. } => Ok(self.build(object, false)?.remove(0)),
+            _ => bail!(
+                "CSG operands and medium boundaries must be closed solids (spheres, boxes, \
+                 cylinders, cones, tori or CSG objects)"
+            ),
+        }
+    }
+}
+
+impl SceneDesc {
+    fn config(&self, base_dir: &Path, files: &mut Fingerprint) -> Result<Config> {
+        let defaults = Config::default();
+        let camera = &self.camera;
+        let render = &self.render;
+        let lookfrom = camera.lookfrom.map_or(defaults.lookfrom, vec3);
+        let lookto = camera.lookto.map_or(defaults.lookto, vec3);
+        let vup = camera.vup.map_or(defaults.vup, vec3);
+        if vup.is_near_zero() {
+            bail!("vup must not be zero");
+        }
+        if vup
+            .as_unit()
+            .cross((lookto - lookfrom).as_unit())
+            .is_near_zero()
+        {
+            bail!("vup must not be parallel to the view direction");
+        }
+        let environment: Arc<dyn Environment> = match &self.environment {
+            None => defaults.environment.clone(),
+            Some(EnvironmentDesc::Constant { color }) => ConstantEnvironment::new(vec3(*color)),
+            Some(EnvironmentDesc::Gradient { bottom, top }) => {
+                Gradient::new(vec3(*bottom), vec3(*top))
+            }
+            Some(EnvironmentDesc::Map {
+                path,
+                rotation,
+                intensity,
+            }) => {
+                let image = load_image(&base_dir.join(path), files)?;
+                if image.width() == 0 || image.height() == 0 {
+                    bail!("the environment map {} is empty", path.display());
+                }
+                EnvironmentMap::new(image, rotation.to_radians(), *intensity)
+            }
+            Some(EnvironmentDesc::Sky {
+                sun_direction,
+                turbidity,
+                ground_albedo,
+            }) => {
+                if !(2.0..=10.0).contains(turbidity) {
+                    bail!("sky turbidity must be between 2 and 10");
+                }
+                let sun_direction = vec3(*sun_direction);
+                if sun_direction.is_near_zero() {
+                    bail!("sky sun_direction must not be zero");
+                }
+                Sky::new(sun_direction, *turbidity, vec3(*ground_albedo))
+            }
+        };
+        let adaptive = render
+            .adaptive
+            .as_ref()
+            .map(AdaptiveDesc::build)
+            .transpose()?;
+        let atmosphere = self
+            .atmosphere
+            .as_ref()
+            .map(AtmosphereDesc::build)
+            .transpose()?;
+        Ok(Config {
+            lookfrom,
+            lookto,
+            vup,
+            vertical_fov: camera
+                .vertical_fov
+                .map_or(defaults.vertical_fov, f64::to_radians),
+            aperture: camera.aperture.unwrap_or(defaults.aperture),
+            focus_dist: camera.focus_dist.unwrap_or(defaults.focus_dist),
+            shutter_open: camera.shutter_open.unwrap_or(defaults.shutter_open),
+            shutter_close: camera.shutter_close.unwrap_or(defaults.shutter_close),
+            samples_per_pixel: render
+                .samples_per_pixel
+                .unwrap_or(defaults.samples_per_pixel),
+            bounce_limit: render.bounce_limit.unwrap_or(defaults.bounce_limit),
+            seed: render.seed.unwrap_or(defaults.seed),
+            sampler: match render.sampler {
+                None => defaults.sampler,
+                Some(SamplerDesc::Independent) => SamplerKind::Independent,
+                Some(SamplerDesc::Stratified) => SamplerKind::Stratified,
+                Some(SamplerDesc::Halton) => SamplerKind::Halton,
+                Some(SamplerDesc::Sobol) => SamplerKind::Sobol,
+            },
+            adaptive,
+            environment,
+            atmosphere,
+            ..defaults
+        })
+    }
+}
+
+// loads the image at path, adding it to files
+fn load_image(path: &Path, files: &mut Fingerprint) -> Result<Bitmap> {
+    files.add_file(path)?;
+    Bitmap::load(path)
+}
+
+// Builds the named texture and the textures it refers to into textures, with stack holding the
+// textures being built, to detect cycles.
+fn build_texture<'a>(
+    descs: &'a HashMap<String, TextureDesc>,
+    name: &'a str,
+    base_dir: &Path,
+    textures: &mut HashMap<&'a str, Arc<dyn Texture>>,
+    stack: &mut Vec<&'a str>,
+    files: &mut Fingerprint,
+) -> Result<Arc<dyn Texture>> {
+    if let Some(texture) = textures.get(name) {
+        return Ok(Arc::clone(texture));
+    }
+    if stack.contains(&name) {
+        bail!("texture `{name}` refers back to itself");
+    }
+    let desc = descs
+        .get(name)
+        .ok_or_else(|| anyhow!("unknown texture `{name}`"))?;
+
+    stack.push(name);
+    let mut color = |desc: &'a ColorDesc| -> Result<Arc<dyn Texture>> {
+        match desc {
+            ColorDesc::Constant(color) => Ok(SolidColor::new(vec3(*color))),
+            ColorDesc::Texture(name) => {
+                build_texture(descs, name, base_dir, textures, stack, files)
+            }
+        }
+    };
+    let texture: Arc<dyn Texture> = match desc {
+        TextureDesc::Checker { size, even, odd } => Checker::new(*size, color(even)?, color(odd)?),
+        TextureDesc::Image { path, wrap } => {
+            let wrap = match wrap {
+                WrapDesc::Repeat => WrapMode::Repeat,
+                WrapDesc::Clamp => WrapMode::Clamp,
+                WrapDesc::Mirror => WrapMode::Mirror,
+            };
+            ImageTexture::new(load_image(&base_dir.join(path), files)?, wrap)
+        }
+        TextureDesc::Noise { scale, pattern } => {
+            let pattern = match pattern {
+                PatternDesc::Noise => NoisePattern::Noise,
+                PatternDesc::Turbulence => NoisePattern::Turbulence,
+                PatternDesc::Marble => NoisePattern::Marble,
+            };
+            NoiseTexture::new(*scale, pattern)
+        }
+    };
+    stack.pop();
+
+    textures.insert(name, Arc::clone(&texture));
+    Ok(texture)
+}
+
+// the object moved by the transforms, if there are any
+fn place(object: Arc<dyn Hittable>, descs: &[TransformDesc]) -> Result<Arc<dyn Hittable>> {
+    if descs.is_empty() {
+        return Ok(object);
+    }
+    Ok(Instance::new(object, build_transform(descs)?))
+}
+
+// the transforms applied in order
+fn build_transform(descs: &[TransformDesc]) -> Result<Transform> {
+    descs
+        .iter()
+        .try_fold(Transform::IDENTITY, |transform, desc| {
+            let next = match *desc {
+                TransformDesc::Translate(offset) => Transform::translate(vec3(offset)),
+                TransformDesc::Scale(ref scale) => Transform::scale(scale.factors()?),
+                TransformDesc::Rotate(ref rotation) => {
+                    let axis = rotation.axis()?;
+                    Transform::rotate(axis, rotation.angle.to_radians())
+                }
+            };
+            Ok(transform.then(next))
+        })
+}
+
+impl ScaleDesc {
+    fn factors(&self) -> Result<Vec3> {
+        let factors = match *self {
+            ScaleDesc::Uniform(factor) => Vec3::new(factor, factor, factor),
+            ScaleDesc::PerAxis(factors) => vec3(factors),
+        };
+        if factors.x() == 0.0 || factors.y() == 0.0 || factors.z() == 0.0 {
+            bail!("scale factors must be non-zero");
+        }
+        Ok(factors)
+    }
+}
+
+impl RotationDesc {
+    fn axis(&self) -> Result<Vec3> {
+        if vec3(self.axis).is_near_zero() {
+            bail!("rotation axis must be non-zero");
+        }
+        Ok(vec3(self.axis))
+    }
+}
+
+impl KeyframeDesc {
+    fn build(&self) -> Result<Keyframe> {
+        let scale = match &self.scale {
+            Some(scale) => scale.factors()?,
+            None => Vec3::new(1.0, 1.0, 1.0),
+        };
+        let rotation = match &self.rotate {
+            Some(rotation) => {
+                Quaternion::from_axis_angle(rotation.axis()?, rotation.angle.to_radians())
+            }
+            None => Quaternion::IDENTITY,
+        };
+        Ok(Keyframe::new(
+            self.time,
+            scale,
+            rotation,
+            vec3(self.translate),
+        ))
+    }
+}
+
+impl AdaptiveDesc {
+    fn build(&self) -> Result<AdaptiveSampling> {
+        let defaults = AdaptiveSampling::default();
+        let adaptive = AdaptiveSampling {
+            min_samples: self.min_samples.unwrap_or(defaults.min_samples),
+            max_samples: self.max_samples.unwrap_or(defaults.max_samples),
+            noise_threshold: self.noise_threshold.unwrap_or(defaults.noise_threshold),
+        };
+        if adaptive.min_samples < 2 {
+            bail!("adaptive sampling needs at least 2 samples per pixel to estimate the noise");
+        }
+        if adaptive.max_samples < adaptive.min_samples {
+            bail!("max_samples must not be less than min_samples");
+        }
+        if adaptive.noise_threshold <= 0.0 {
+            bail!("noise_threshold must be positive");
+        }
+        Ok(adaptive)
+    }
+}
+
+impl AtmosphereDesc {
+    fn build(&self) -> Result<Atmosphere> {
+        if self.density < 0.0 {
+            bail!("atmosphere density must not be negative");
+        }
+        if self.anisotropy.abs() >= 1.0 {
+            bail!("anisotropy must be between -1 and 1");
+        }
+        let albedo = vec3(self.albedo);
+        let phase_function: Arc<dyn Material> = if self.anisotropy == 0.0 {
+            Isotropic::new(albedo)
+        } else {
+            HenyeyGreenstein::new(albedo, self.anisotropy)
+        };
+        Ok(Atmosphere::new(self.density, self.height, phase_function))
+    }
+}
+
+impl ColorDesc {
+    fn build(&self, textures: &HashMap<&str, Arc<dyn Texture>>) -> Result<Arc<dyn Texture>> {
+        match self {
+            Self::Constant(color) => Ok(SolidColor::new(vec3(*color))),
+            Self::Texture(name) => textures
+                .get(name.as_str())
+                .cloned()
+                .ok_or_else(|| anyhow!("unknown texture `{name}`")),
+        }
+    }
+}
+
+impl MaterialDesc {
+    fn build(&self, textures: &HashMap<&str, Arc<dyn Texture>>) -> Result<Arc<dyn Material>> {
+        Ok(match *self {
+            Self::Lambertian { ref albedo } => ApproxLambertian::textured(albedo.build(textures)?),
+            Self::Metal { ref albedo, fuzz } => Metal::textured(albedo.build(textures)?, fuzz),
+            Self::Dielectric { refraction_index } => Dielectric::new(refraction_index),
+            Self::DiffuseLight {
+                color,
+                intensity,
+                one_sided: false,
+            } => DiffuseLight::new(vec3(color), intensity),
+            Self::DiffuseLight {
+                color,
+                intensity,
+                one_sided: true,
+            } => DiffuseLight::one_sided(vec3(color), intensity),
+            Self::Isotropic { ref albedo } => Isotropic::textured(albedo.build(textures)?),
+            Self::HenyeyGreenstein {
+                ref albedo,
+                anisotropy,
+            } => {
+                if anisotropy.abs() >= 1.0 {
+                    bail!("anisotropy must be between -1 and 1");
+                }
+                HenyeyGreenstein::textured(albedo.build(textures)?, anisotropy)
+            }
+        })
+    }
+}
+
+fn default_albedo() -> Triple {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_end_time() -> f64 {
+    1.0
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn default_ground_albedo() -> Triple {
+    [0.3, 0.3, 0.3]
+}
+
+fn default_bottom() -> Triple {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_top() -> Triple {
+    [0.5, 0.7, 1.0]
+}
+
+fn vec3([x, y, z]: Triple) -> Vec3 {
+    Vec3::new(x, y, z)
+}
@@ -0,0 +1,723 @@
+// Analytic solids beyond the sphere: capped cylinders and cones, tori and general quadrics.
+//
+// Cylinders, cones and tori are intersected in a local frame with their axis along z, which keeps
+// the equations in their simplest (canonical) form.
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// A cylinder capped at both ends, from base along axis (whose length is the height). Its sides
+// are mapped to u around the axis and v along it, and its caps to u around the axis and v out
+// from the centre.
+pub struct Cylinder {
+    frame: Frame,
+    height: f64,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
+
+// A cone with a circular base of radius at base, narrowing to its apex at base + axis. It's
+// mapped like a cylinder.
+pub struct Cone {
+    frame: Frame,
+    height: f64,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
+
+// A ring of radius major_radius around axis through centre, swept by a circle of radius
+// minor_radius. u is the angle around the axis and v the angle around the tube.
+pub struct Torus {
+    frame: Frame,
+    major_radius: f64,
+    minor_radius: f64,
+    material: Arc<dyn Material>,
+}
+
+// The surface of points (x, y, z) where
+//
+//     a x^2 + b y^2 + c z^2 + d xy + e xz + f yz + g x + h y + i z + j = 0
+//
+// for coefficients [a, ..., j], optionally clipped to the bounds. The outward normal points to
+// where the left side is positive. u and v are the spherical angles (as fractions of a turn and
+// of a half turn) around the centre of the bounds, or the origin.
+pub struct Quadric {
+    // the symmetric matrix Q with the equation p^T Q p = 0 for p = (x, y, z, 1)
+    matrix: Mat4,
+    bounds: Option<Aabb>,
+    material: Arc<dyn Material>,
+}
+
+// An orthonormal frame with its third axis along a given direction
+struct Frame {
+    origin: Point3,
+    axes: [Vec3; 3],
+}
+
+impl Frame {
+    fn new(origin: Point3, axis: Vec3) -> Self {
+        let axis = axis.as_unit();
+        let (s, t) = axis.basis();
+        Self {
+            origin,
+            axes: [s, t, axis],
+        }
+    }
+
+    fn ray_to_local(&self, ray: &Ray) -> Ray {
+        Ray::new(
+            self.vector_to_local(ray.origin - self.origin),
+            self.vector_to_local(ray.direction),
+            ray.time,
+        )
+    }
+
+    fn vector_to_local(&self, vector: Vec3) -> Vec3 {
+        let [s, t, a] = self.axes;
+        Vec3::new(vector.dot(s), vector.dot(t), vector.dot(a))
+    }
+
+    fn vector_to_world(&self, vector: Vec3) -> Vec3 {
+        let [s, t, a] = self.axes;
+        vector.x() * s + vector.y() * t + vector.z() * a
+    }
+
+    // the bounds of a circle of radius around centre (in world space) in the plane of the frame
+    fn circle_bounds(&self, centre: Point3, radius: f64) -> Aabb {
+        let a = self.axes[2];
+        let reach = |axis: f64| radius * (1.0 - axis * axis).max(0.0).sqrt();
+        let extent = Vec3::new(reach(a.x()), reach(a.y()), reach(a.z()));
+        Aabb::new(centre - extent, centre + extent)
+    }
+}
+
+// A candidate intersection, in a shape's local space
+struct LocalHit {
+    t: f64,
+    normal: Vec3,
+    uv: (f64, f64),
+}
+
+impl LocalHit {
+    fn into_result(
+        self,
+        ray: &Ray,
+        frame: &Frame,
+        material: &Arc<dyn Material>,
+    ) -> Option<HitResult> {
+        let normal = frame.vector_to_world(self.normal).as_unit();
+        HitResult::new(
+            HitRecord::from_outward_normal(self.t, ray.at(self.t), ray.direction, normal)
+                .with_uv(self.uv.0, self.uv.1),
+            Arc::clone(material),
+        )
+    }
+}
+
+// the nearest of the crossings within (t_min, t_max)
+fn nearest_in(crossings: Vec<LocalHit>, t_min: f64, t_max: f64) -> Option<LocalHit> {
+    crossings
+        .into_iter()
+        .filter(|hit| t_min <= hit.t && hit.t <= t_max)
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, axis: Vec3, radius: f64, material: Arc<dyn Material>) -> Arc<Self> {
+        Arc::new(Self {
+            frame: Frame::new(base, axis),
+            height: axis.length(),
+            radius,
+            material,
+        })
+    }
+
+    // where the whole line of the ray (in local space) crosses the surface
+    fn crossings(&self, local: &Ray) -> Vec<LocalHit> {
+        let (o, d) = (local.origin, local.direction);
+
+        // x^2 + y^2 = r^2 for 0 <= z <= height
+        let a = d.x() * d.x() + d.y() * d.y();
+        let b = 2.0 * (o.x() * d.x() + o.y() * d.y());
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        let mut crossings: Vec<_> = solve_quadratic(a, b, c)
+            .into_iter()
+            .flatten()
+            .map(|t| (t, local.at(t)))
+            .filter(|(_, p)| (0.0..=self.height).contains(&p.z()))
+            .map(|(t, p)| LocalHit {
+                t,
+                normal: Vec3::new(p.x(), p.y(), 0.0),
+                uv: (angle_fraction(p), p.z() / self.height),
+            })
+            .collect();
+
+        crossings.extend(cap(local, 0.0, -1.0, self.radius));
+        crossings.extend(cap(local, self.height, 1.0, self.radius));
+        crossings
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let local = self.frame.ray_to_local(ray);
+        nearest_in(self.crossings(&local), t_min, t_max)?.into_result(
+            ray,
+            &self.frame,
+            &self.material,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let top = self.frame.origin + self.height * self.frame.axes[2];
+        let ends = self
+            .frame
+            .circle_bounds(self.frame.origin, self.radius)
+            .union(self.frame.circle_bounds(top, self.radius));
+        Some(ends.padded())
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let local = self.frame.ray_to_local(ray);
+        let hits = self
+            .crossings(&local)
+            .into_iter()
+            .filter_map(|hit| hit.into_result(ray, &self.frame, &self.material))
+            .collect();
+        Some(Span::convex(hits).into_iter().collect())
+    }
+}
+
+impl Cone {
+    pub fn new(base: Point3, axis: Vec3, radius: f64, material: Arc<dyn Material>) -> Arc<Self> {
+        Arc::new(Self {
+            frame: Frame::new(base, axis),
+            height: axis.length(),
+            radius,
+            material,
+        })
+    }
+
+    // where the whole line of the ray (in local space) crosses the surface
+    fn crossings(&self, local: &Ray) -> Vec<LocalHit> {
+        let (o, d) = (local.origin, local.direction);
+
+        // x^2 + y^2 = k^2 (height - z)^2 for 0 <= z <= height, with k the slope of the sides
+        let k_sq = (self.radius / self.height).powi(2);
+        let (h_o, h_d) = (self.height - o.z(), -d.z());
+        let a = d.x() * d.x() + d.y() * d.y() - k_sq * h_d * h_d;
+        let b = 2.0 * (o.x() * d.x() + o.y() * d.y() - k_sq * h_o * h_d);
+        let c = o.x() * o.x() + o.y() * o.y() - k_sq * h_o * h_o;
+        let mut crossings: Vec<_> = solve_quadratic(a, b, c)
+            .into_iter()
+            .flatten()
+            .map(|t| (t, local.at(t)))
+            // the equation also holds on the mirrored cone above the apex
+            .filter(|(_, p)| (0.0..=self.height).contains(&p.z()))
+            .map(|(t, p)| LocalHit {
+                t,
+                normal: Vec3::new(p.x(), p.y(), k_sq * (self.height - p.z())),
+                uv: (angle_fraction(p), p.z() / self.height),
+            })
+            .collect();
+
+        crossings.extend(cap(local, 0.0, -1.0, self.radius));
+        crossings
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let local = self.frame.ray_to_local(ray);
+        nearest_in(self.crossings(&local), t_min, t_max)?.into_result(
+            ray,
+            &self.frame,
+            &self.material,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let apex = self.frame.origin + self.height * self.frame.axes[2];
+        let base = self.frame.circle_bounds(self.frame.origin, self.radius);
+        Some(base.union(Aabb::new(apex, apex)).padded())
+    }
+
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let local = self.frame.ray_to_local(ray);
+        let hits = self
+            .crossings(&local)
+            .into_iter()
+            .filter_map(|hit| hit.into_result(ray, &self.frame, &self.material))
+            .collect();
+        Some(Span::convex(hits).into_iter().collect())
+    }
+}
+
+// where the line of the ray crosses a cap of radius at height z in local space, facing along the
+// z axis (by sign)
+fn cap(local: &Ray, z: f64, sign: f64, radius: f64) -> Option<LocalHit> {
+    if local.direction.z() == 0.0 {
+        return None;
+    }
+    let t = (z - local.origin.z()) / local.direction.z();
+    let p = local.at(t);
+    let distance = (p.x() * p.x() + p.y() * p.y()).sqrt();
+    (distance <= radius).then(|| LocalHit {
+        t,
+        normal: Vec3::new(0.0, 0.0, sign),
+        uv: (angle_fraction(p), distance / radius),
+    })
+}
+
+impl Torus {
+    // axis is the direction the hole of the ring runs along
+    pub fn new(
+        centre: Point3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            frame: Frame::new(centre, axis),
+            major_radius,
+            minor_radius,
+            material,
+        })
+    }
+
+    // where the whole line of the ray (in local space) crosses the surface, in order
+    fn crossings(&self, local: &Ray) -> Vec<LocalHit> {
+        let length = local.direction.length();
+        let d = local.direction / length;
+        // Solving from the point of the ray nearest the centre keeps the coefficients small
+        // (and the roots accurate) for rays starting far away.
+        let shift = -local.origin.dot(d);
+        let o = local.origin + shift * d;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2), a quartic in the distance s along d
+        let (r_sq, rho_sq) = (self.major_radius.powi(2), self.minor_radius.powi(2));
+        let m = o.length_sq();
+        let n = o.dot(d);
+        let k = m + r_sq - rho_sq;
+        let coefficients = [
+            k * k - 4.0 * r_sq * (m - o.z() * o.z()),
+            4.0 * n * k - 8.0 * r_sq * (n - o.z() * d.z()),
+            4.0 * n * n + 2.0 * k - 4.0 * r_sq * (1.0 - d.z() * d.z()),
+            4.0 * n,
+            1.0,
+        ];
+        polynomial_roots(&coefficients)
+            .into_iter()
+            .map(|s| {
+                let t = (s + shift) / length;
+                let p = local.at(t);
+                let k = p.length_sq() + r_sq - rho_sq;
+                let normal = k * p - 2.0 * r_sq * Vec3::new(p.x(), p.y(), 0.0);
+                let ring_distance = (p.x() * p.x() + p.y() * p.y()).sqrt() - self.major_radius;
+                let tube_angle = p.z().atan2(ring_distance).rem_euclid(2.0 * PI);
+                LocalHit {
+                    t,
+                    normal,
+                    uv: (angle_fraction(p), tube_angle / (2.0 * PI)),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let local = self.frame.ray_to_local(ray);
+        nearest_in(self.crossings(&local), t_min, t_max)?.into_result(
+            ray,
+            &self.frame,
+            &self.material,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let ring = self
+            .frame
+            .circle_bounds(self.frame.origin, self.major_radius);
+        let tube = Vec3::new(self.minor_radius, self.minor_radius, self.minor_radius);
+        Some(Aabb::new(ring.min - tube, ring.max + tube))
+    }
+
+    // The ray passes in and out of the tube once or twice. Grazing it (a double root) doesn't
+    // count as a crossing, as the root finder skips it.
+    fn spans(&self, ray: &Ray) -> Option<Vec<Span>> {
+        let local = self.frame.ray_to_local(ray);
+        let hits: Vec<_> = self
+            .crossings(&local)
+            .into_iter()
+            .filter_map(|hit| hit.into_result(ray, &self.frame, &self.material))
+            .collect();
+        let mut hits = hits.into_iter();
+        let mut spans = vec![];
+        while let (Some(enter), Some(exit)) = (hits.next(), hits.next()) {
+            spans.push(Span { enter, exit });
+        }
+        Some(spans)
+    }
+}
+
+impl Quadric {
+    pub fn new(
+        [a, b, c, d, e, f, g, h, i, j]: [f64; 10],
+        bounds: Option<Aabb>,
+        material: Arc<dyn Material>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            matrix: Mat4([
+                [a, d / 2.0, e / 2.0, g / 2.0],
+                [d / 2.0, b, f / 2.0, h / 2.0],
+                [e / 2.0, f / 2.0, c, i / 2.0],
+                [g / 2.0, h / 2.0, i / 2.0, j],
+            ]),
+            bounds,
+            material,
+        })
+    }
+
+    // the bilinear form x^T Q y
+    fn form(&self, x: [f64; 4], y: [f64; 4]) -> f64 {
+        (0..4)
+            .map(|row| {
+                x[row]
+                    * (0..4)
+                        .map(|col| self.matrix.0[row][col] * y[col])
+                        .sum::<f64>()
+            })
+            .sum()
+    }
+}
+
+impl Hittable for Quadric {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        let (o, d) = (ray.origin, ray.direction);
+        let o4 = [o.x(), o.y(), o.z(), 1.0];
+        let d4 = [d.x(), d.y(), d.z(), 0.0];
+
+        // (o + t d)^T Q (o + t d) = 0
+        let a = self.form(d4, d4);
+        let b = 2.0 * self.form(d4, o4);
+        let c = self.form(o4, o4);
+        let inside_bounds = |p: Point3| {
+            self.bounds.is_none_or(|bounds| {
+                (0..3).all(|axis| {
+                    (bounds.min.axis(axis)..=bounds.max.axis(axis)).contains(&p.axis(axis))
+                })
+            })
+        };
+        let (t, p) = solve_quadratic(a, b, c)
+            .into_iter()
+            .flatten()
+            .filter(|&t| t_min <= t && t <= t_max)
+            .map(|t| (t, ray.at(t)))
+            .find(|&(_, p)| inside_bounds(p))?;
+
+        // the gradient of the equation, (Q p)_xyz up to a factor of 2
+        let q = self.matrix;
+        let p4 = [p.x(), p.y(), p.z(), 1.0];
+        let gradient = |row: usize| (0..4).map(|col| q.0[row][col] * p4[col]).sum::<f64>();
+        let normal = Vec3::new(gradient(0), gradient(1), gradient(2));
+        if normal.is_near_zero() {
+            return None;
+        }
+
+        let centre = self.bounds.map_or(Point3::ZERO, |bounds| bounds.centroid());
+        let r = p - centre;
+        let u = angle_fraction(Vec3::new(r.x(), r.z(), 0.0));
+        let v = (r.y() / r.length()).clamp(-1.0, 1.0).acos() / PI;
+        HitResult::new(
+            HitRecord::from_outward_normal(t, p, d, normal.as_unit()).with_uv(u, v),
+            Arc::clone(&self.material),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds.map(Aabb::padded)
+    }
+}
+
+// the angle of p around the z axis as a fraction of a turn
+fn angle_fraction(p: Vec3) -> f64 {
+    p.y().atan2(p.x()).rem_euclid(2.0 * PI) / (2.0 * PI)
+}
+
+// The real roots of a x^2 + b x + c in increasing order, with a double root repeated. A
+// vanishing quadratic term leaves the linear root.
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<[f64; 2]> {
+    if a.abs() < 1e-12 {
+        if b == 0.0 {
+            return None;
+        }
+        return Some([-c / b; 2]);
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    // avoids the cancellation of subtracting nearly equal terms
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    let (x0, x1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some([x0.min(x1), x0.max(x1)])
+}
+
+// The real roots of the polynomial with coefficients given from the constant term up, in
+// increasing order. The roots of the derivative split the real line into intervals on which the
+// polynomial is monotonic, each holding at most one root, which is then found by bisection.
+fn polynomial_roots(coefficients: &[f64]) -> Vec<f64> {
+    let degree = coefficients.len() - 1;
+    let leading = coefficients[degree];
+    if degree == 1 {
+        return vec![-coefficients[0] / leading];
+    }
+
+    // all roots lie within Cauchy's bound
+    let bound = 1.0
+        + coefficients[..degree]
+            .iter()
+            .map(|c| (c / leading).abs())
+            .fold(0.0, f64::max);
+    let derivative: Vec<f64> = coefficients[1..]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i + 1) as f64 * c)
+        .collect();
+    let mut ends = vec![-bound];
+    ends.extend(
+        polynomial_roots(&derivative)
+            .into_iter()
+            .map(|x| x.clamp(-bound, bound)),
+    );
+    ends.push(bound);
+
+    let eval = |x: f64| coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c);
+    let mut roots: Vec<f64> = vec![];
+    for pair in ends.windows(2) {
+        let (mut lo, mut hi) = (pair[0], pair[1]);
+        let (f_lo, f_hi) = (eval(lo), eval(hi));
+        if f_hi == 0.0 {
+            roots.push(hi);
+            continue;
+        }
+        // a root at lo was already found as the end of the previous interval
+        if f_lo == 0.0 || f_lo.signum() == f_hi.signum() {
+            continue;
+        }
+        for _ in 0..100 {
+            let mid = 0.5 * (lo + hi);
+            if mid == lo || mid == hi {
+                break;
+            }
+            if eval(mid).signum() == f_lo.signum() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        roots.push(0.5 * (lo + hi));
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::ApproxLambertian;
+    use crate::sampler::SamplerKind;
+
+    fn material() -> Arc<dyn Material> {
+        ApproxLambertian::new(Color::new(0.5, 0.5, 0.5))
+    }
+
+    fn hit(object: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let ray = Ray::new(origin, direction, 0.0);
+        object
+            .hit(
+                &ray,
+                1e-6,
+                f64::INFINITY,
+                &mut *SamplerKind::default().create(0, 1),
+            )
+            .map(|result| result.record)
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn cylinder_side_and_caps() {
+        let cylinder = Cylinder::new(Point3::ZERO, Vec3::new(0, 0, 2), 1.0, material());
+
+        let side = hit(&*cylinder, Point3::new(-5, 0, 1), Vec3::new(1, 0, 0)).unwrap();
+        assert!((side.t - 4.0).abs() < 1e-9);
+        assert_near(side.point, Point3::new(-1, 0, 1));
+        assert_near(side.normal, Vec3::new(-1, 0, 0));
+        assert!(side.front_face);
+        assert!((side.v - 0.5).abs() < 1e-9);
+
+        let top = hit(&*cylinder, Point3::new(0.5, 0, 5), Vec3::new(0, 0, -1)).unwrap();
+        assert!((top.t - 3.0).abs() < 1e-9);
+        assert_near(top.normal, Vec3::new(0, 0, 1));
+        assert!((top.v - 0.5).abs() < 1e-9);
+
+        let bottom = hit(&*cylinder, Point3::new(0, 0, -1), Vec3::new(0, 0, 1)).unwrap();
+        assert_near(bottom.point, Point3::ZERO);
+        assert_near(bottom.normal, Vec3::new(0, 0, -1));
+
+        // above the top and passing beside it
+        assert!(hit(&*cylinder, Point3::new(-5, 0, 3), Vec3::new(1, 0, 0)).is_none());
+        assert!(hit(&*cylinder, Point3::new(-5, 2, 1), Vec3::new(1, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn cylinder_from_inside() {
+        let cylinder = Cylinder::new(Point3::ZERO, Vec3::new(0, 0, 2), 1.0, material());
+        let record = hit(&*cylinder, Point3::new(0, 0, 1), Vec3::new(1, 0, 0)).unwrap();
+        assert!((record.t - 1.0).abs() < 1e-9);
+        assert!(!record.front_face);
+        // facing against the ray
+        assert_near(record.normal, Vec3::new(-1, 0, 0));
+    }
+
+    #[test]
+    fn tilted_cylinder() {
+        // along x from (1, 0, 0) to (3, 0, 0)
+        let cylinder = Cylinder::new(Point3::new(1, 0, 0), Vec3::new(2, 0, 0), 0.5, material());
+        let record = hit(&*cylinder, Point3::new(2, 5, 0), Vec3::new(0, -1, 0)).unwrap();
+        assert_near(record.point, Point3::new(2, 0.5, 0));
+        assert_near(record.normal, Vec3::new(0, 1, 0));
+        let bbox = cylinder.bounding_box().unwrap();
+        assert!((bbox.min.x() - 1.0).abs() < 1e-5 && (bbox.max.x() - 3.0).abs() < 1e-5);
+        assert!((bbox.max.y() - 0.5).abs() < 1e-5 && (bbox.max.z() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cone_side_and_base() {
+        let cone = Cone::new(Point3::ZERO, Vec3::new(0, 0, 2), 1.0, material());
+
+        // the radius halves halfway up
+        let side = hit(&*cone, Point3::new(-5, 0, 1), Vec3::new(1, 0, 0)).unwrap();
+        assert!((side.t - 4.5).abs() < 1e-9);
+        assert_near(side.point, Point3::new(-0.5, 0, 1));
+        assert_near(side.normal, Vec3::new(-2, 0, 1).as_unit());
+        assert!(side.front_face);
+
+        let base = hit(&*cone, Point3::new(0.2, 0, -3), Vec3::new(0, 0, 1)).unwrap();
+        assert!((base.t - 3.0).abs() < 1e-9);
+        assert_near(base.normal, Vec3::new(0, 0, -1));
+
+        // the mirrored nappe above the apex isn't part of the cone
+        assert!(hit(&*cone, Point3::new(-5, 0, 3), Vec3::new(1, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn torus_crossings() {
+        let torus = Torus::new(Point3::ZERO, Vec3::new(0, 0, 1), 2.0, 0.5, material());
+
+        // through both sides of the ring
+        let outer = hit(&*torus, Point3::new(-5, 0, 0), Vec3::new(1, 0, 0)).unwrap();
+        assert!((outer.t - 2.5).abs() < 1e-9);
+        assert_near(outer.normal, Vec3::new(-1, 0, 0));
+        assert!(outer.front_face);
+        let ray = Ray::new(Point3::new(-5, 0, 0), Vec3::new(1, 0, 0), 0.0);
+        let inner = torus
+            .hit(
+                &ray,
+                2.6,
+                f64::INFINITY,
+                &mut *SamplerKind::default().create(0, 1),
+            )
+            .unwrap()
+            .record;
+        assert!((inner.t - 3.5).abs() < 1e-9);
+        assert!(!inner.front_face);
+
+        // down onto the top of the tube
+        let top = hit(&*torus, Point3::new(0, 2, 5), Vec3::new(0, 0, -1)).unwrap();
+        assert!((top.t - 4.5).abs() < 1e-9);
+        assert_near(top.normal, Vec3::new(0, 0, 1));
+        assert!((top.v - 0.25).abs() < 1e-9);
+
+        // through the hole, and out of it
+        assert!(hit(&*torus, Point3::new(0, 0, 5), Vec3::new(0, 0, -1)).is_none());
+        let from_hole = hit(&*torus, Point3::ZERO, Vec3::new(0, 3, 0)).unwrap();
+        assert!((from_hole.t - 0.5).abs() < 1e-9);
+        assert!(from_hole.front_face);
+    }
+
+    #[test]
+    fn torus_from_far_away() {
+        let torus = Torus::new(
+            Point3::new(1, 2, 3),
+            Vec3::new(0, 1, 0),
+            2.0,
+            0.5,
+            material(),
+        );
+        let record = hit(&*torus, Point3::new(1e4, 2, 3), Vec3::new(-1, 0, 0)).unwrap();
+        assert_near(record.point, Point3::new(3.5, 2, 3));
+    }
+
+    #[test]
+    fn quadric_sphere_and_hyperboloid() {
+        let sphere = Quadric::new(
+            [1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            None,
+            material(),
+        );
+        let record = hit(&*sphere, Point3::new(0, 0, -5), Vec3::new(0, 0, 1)).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-9);
+        assert_near(record.normal, Vec3::new(0, 0, -1));
+        assert!(record.front_face);
+
+        // x^2 + y^2 - z^2 = 1, whose waist is the unit circle
+        let hyperboloid = Quadric::new(
+            [1.0, 1.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            None,
+            material(),
+        );
+        let record = hit(&*hyperboloid, Point3::new(-5, 0, 0), Vec3::new(1, 0, 0)).unwrap();
+        assert_near(record.point, Point3::new(-1, 0, 0));
+        assert_near(record.normal, Vec3::new(-1, 0, 0));
+        let record = hit(&*hyperboloid, Point3::new(-5, 0, 1), Vec3::new(1, 0, 0)).unwrap();
+        assert_near(record.point, Point3::new(-(2f64.sqrt()), 0, 1));
+    }
+
+    #[test]
+    fn quadric_bounds() {
+        // an infinite cylinder along z, clipped to |z| <= 1
+        let bounds = Aabb::new(Point3::new(-1, -1, -1), Point3::new(1, 1, 1));
+        let cylinder = Quadric::new(
+            [1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+            Some(bounds),
+            material(),
+        );
+        assert!(hit(&*cylinder, Point3::new(-5, 0, 0.5), Vec3::new(1, 0, 0)).is_some());
+        assert!(hit(&*cylinder, Point3::new(-5, 0, 2), Vec3::new(1, 0, 0)).is_none());
+        // looking down the open end finds nothing within the bounds
+        assert!(hit(&*cylinder, Point3::new(0, 0, 5), Vec3::new(0, 0, -1)).is_none());
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = polynomial_roots(&[24.0, -50.0, 35.0, -10.0, 1.0]);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        // x^4 + 1 has no real roots
+        assert!(polynomial_roots(&[1.0, 0.0, 0.0, 0.0, 1.0]).is_empty());
+    }
+}
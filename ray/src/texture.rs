@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::bitmap::Bitmap;
+use crate::vec::*;
+
+// A color varying over a surface, looked up by the surface (u, v) coordinates or the hit point
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color;
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+// A 3D checkerboard of cubes of the given size, alternating between two textures
+pub struct Checker {
+    inv_size: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+// How image lookups outside [0, 1] are mapped back onto the image
+#[derive(Clone, Copy, Debug, Default)]
+pub enum WrapMode {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+// A bilinearly filtered image, with (0, 0) at its bottom left corner and (1, 1) at its top right
+pub struct ImageTexture {
+    image: Bitmap,
+    wrap: WrapMode,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum NoisePattern {
+    // smooth Perlin noise
+    Noise,
+    // a sum of octaves of noise
+    Turbulence,
+    // veins of turbulence running along the z axis
+    Marble,
+}
+
+// A grayscale procedural texture built from Perlin noise, with features of roughly 1 / scale
+pub struct NoiseTexture {
+    perlin: Perlin,
+    scale: f64,
+    pattern: NoisePattern,
+}
+
+struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Arc<Self> {
+        Arc::new(Self { color })
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: f64, _: f64, _: Point3) -> Color {
+        self.color
+    }
+}
+
+impl Checker {
+    pub fn new(size: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Arc<Self> {
+        Arc::new(Self {
+            inv_size: 1.0 / size,
+            even,
+            odd,
+        })
+    }
+
+    pub fn from_colors(size: f64, even: Color, odd: Color) -> Arc<Self> {
+        Self::new(size, SolidColor::new(even), SolidColor::new(odd))
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        let cell = |x: f64| (self.inv_size * x).floor() as i64;
+        let sum = cell(point.x()) + cell(point.y()) + cell(point.z());
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+impl ImageTexture {
+    pub fn new(image: Bitmap, wrap: WrapMode) -> Arc<Self> {
+        Arc::new(Self { image, wrap })
+    }
+
+    // the texel at integer coordinates, which may lie outside of the image
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let wrap = |i: i64, n: u32| -> u32 {
+            let n = n as i64;
+            let i = match self.wrap {
+                WrapMode::Repeat => i.rem_euclid(n),
+                WrapMode::Clamp => i.clamp(0, n - 1),
+                WrapMode::Mirror => {
+                    let i = i.rem_euclid(2 * n);
+                    if i < n {
+                        i
+                    } else {
+                        2 * n - 1 - i
+                    }
+                }
+            };
+            i as u32
+        };
+        self.image
+            .get(wrap(x, self.image.width()), wrap(y, self.image.height()))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _: Point3) -> Color {
+        // texel centres are at half-integer coordinates
+        let x = u * self.image.width() as f64 - 0.5;
+        let y = v * self.image.height() as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let bottom = self.texel(x0, y0).lerp(tx, self.texel(x0 + 1, y0));
+        let top = self.texel(x0, y0 + 1).lerp(tx, self.texel(x0 + 1, y0 + 1));
+        bottom.lerp(ty, top)
+    }
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, pattern: NoisePattern) -> Arc<Self> {
+        Arc::new(Self {
+            // from a fixed seed, so that renders are reproducible
+            perlin: Perlin::new(&mut Pcg32::seed_from_u64(0)),
+            scale,
+            pattern,
+        })
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _: f64, _: f64, point: Point3) -> Color {
+        let p = self.scale * point;
+        let value = match self.pattern {
+            NoisePattern::Noise => 0.5 * (1.0 + self.perlin.noise(p)),
+            NoisePattern::Turbulence => self.perlin.turbulence(p, 7),
+            NoisePattern::Marble => {
+                0.5 * (1.0 + (p.z() + 10.0 * self.perlin.turbulence(p, 7)).sin())
+            }
+        };
+        Color::new(value, value, value)
+    }
+}
+
+impl Perlin {
+    const POINT_COUNT: usize = 256;
+
+    fn new(rng: &mut impl Rng) -> Self {
+        let gradients = (0..Self::POINT_COUNT)
+            .map(|_| Vec3::random_range(rng, -1.0..1.0).as_unit())
+            .collect();
+        let mut permutation = || {
+            let mut perm: Vec<usize> = (0..Self::POINT_COUNT).collect();
+            perm.shuffle(rng);
+            perm
+        };
+        Self {
+            gradients,
+            perm_x: permutation(),
+            perm_y: permutation(),
+            perm_z: permutation(),
+        }
+    }
+
+    // gradient noise in [-1, 1]
+    fn noise(&self, p: Point3) -> f64 {
+        let (i, j, k) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - i, p.y() - j, p.z() - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        // Hermite smoothing of the interpolation weights
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+        let mask = Self::POINT_COUNT as i64 - 1;
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[((i + di) & mask) as usize]
+                        ^ self.perm_y[((j + dj) & mask) as usize]
+                        ^ self.perm_z[((k + dk) & mask) as usize]];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    fn turbulence(&self, p: Point3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
@@ -1,11 +1,29 @@
-use rand::Rng;
+use crate::sampler::Sampler;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Vec3(f64, f64, f64);
 
 pub type Color = Vec3;
 pub type Point3 = Vec3;
 
+// A 4x4 matrix of rows, transforming column vectors: points with w = 1 and vectors with w = 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4(pub [[f64; 4]; 4]);
+
+// An affine transform along with its inverse
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+// A unit quaternion, representing a rotation
+#[derive(Clone, Copy, Debug)]
+pub struct Quaternion {
+    w: f64,
+    v: Vec3,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Size {
     pub width: u32,
@@ -86,6 +104,37 @@ impl Vec3 {
         (1.0 - t) * self + (t * rhs)
     }
 
+    pub fn axis(self, axis: usize) -> f64 {
+        match axis {
+            0 => self.0,
+            1 => self.1,
+            2 => self.2,
+            _ => panic!("invalid axis {axis}"),
+        }
+    }
+
+    // component-wise minimum
+    pub fn min(self, rhs: Self) -> Self {
+        Self(self.0.min(rhs.0), self.1.min(rhs.1), self.2.min(rhs.2))
+    }
+
+    // component-wise maximum
+    pub fn max(self, rhs: Self) -> Self {
+        Self(self.0.max(rhs.0), self.1.max(rhs.1), self.2.max(rhs.2))
+    }
+
+    // two unit vectors which together with self (a unit vector) form an orthonormal basis
+    pub fn basis(self) -> (Self, Self) {
+        // Duff et al., "Building an Orthonormal Basis, Revisited"
+        let sign = 1f64.copysign(self.2);
+        let a = -1.0 / (sign + self.2);
+        let b = self.0 * self.1 * a;
+        (
+            Vec3(1.0 + sign * self.0 * self.0 * a, sign * b, -sign * self.0),
+            Vec3(b, sign + self.1 * self.1 * a, -self.1),
+        )
+    }
+
     pub fn is_near_zero(self) -> bool {
         const EPSILON: f64 = 1e-8;
         self.0.abs() < EPSILON && self.1.abs() < EPSILON && self.2.abs() < EPSILON
@@ -99,28 +148,21 @@ impl Vec3 {
         )
     }
 
-    pub fn random_in_unit_sphere() -> Self {
-        let mut rng = rand::thread_rng();
-        loop {
-            let point = Vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-            if point.length_sq() < 1.0 {
-                return point;
-            }
-        }
+    // uniformly distributed over the unit sphere
+    pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Self {
+        let (xi_z, xi_phi) = sampler.get_2d();
+        let z = 1.0 - 2.0 * xi_z;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * xi_phi;
+        Vec3(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_unit_vector() -> Self {
-        Self::random_in_unit_sphere().as_unit()
-    }
-
-    pub fn random_in_unit_disk() -> Self {
-        let mut rng = rand::thread_rng();
-        loop {
-            let point = Self::random_range(&mut rng, -1.0..1.0);
-            if point.length_sq() < 1.0 {
-                return point;
-            }
-        }
+    // uniformly distributed over the unit disk in the xy plane
+    pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Self {
+        let (xi_r, xi_phi) = sampler.get_2d();
+        let r = xi_r.sqrt();
+        let phi = 2.0 * std::f64::consts::PI * xi_phi;
+        Vec3(r * phi.cos(), r * phi.sin(), 0.0)
     }
 }
 
@@ -226,6 +268,296 @@ impl rand::distributions::Distribution<Vec3> for rand::distributions::Standard {
     }
 }
 
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.0[0][3] = offset.0;
+        m.0[1][3] = offset.1;
+        m.0[2][3] = offset.2;
+        m
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut m = Self::IDENTITY;
+        m.0[0][0] = factors.0;
+        m.0[1][1] = factors.1;
+        m.0[2][2] = factors.2;
+        m
+    }
+
+    // rotation by angle (in radians) about a unit axis, counterclockwise looking down the axis
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let Vec3(x, y, z) = axis;
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        Self([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(self) -> Self {
+        let mut m = self;
+        for (i, row) in m.0.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        m
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None if the matrix is singular
+    pub fn inverse(self) -> Option<Self> {
+        let mut m = self.0;
+        let mut inverse = Self::IDENTITY.0;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| m[a][column].abs().total_cmp(&m[b][column].abs()))
+                .unwrap();
+            if m[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            m.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / m[column][column];
+            for j in 0..4 {
+                m[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                let factor = m[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    m[row][j] -= factor * m[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Self(inverse))
+    }
+
+    pub fn transform_point(self, point: Point3) -> Point3 {
+        self.transform_vector(point) + Vec3(self.0[0][3], self.0[1][3], self.0[2][3])
+    }
+
+    pub fn transform_vector(self, vector: Vec3) -> Vec3 {
+        let row = |r: [f64; 4]| r[0] * vector.0 + r[1] * vector.1 + r[2] * vector.2;
+        Vec3(row(self.0[0]), row(self.0[1]), row(self.0[2]))
+    }
+}
+
+impl std::ops::Mul<Mat4> for Mat4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Mat4) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Self(m)
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        matrix: Mat4::IDENTITY,
+        inverse: Mat4::IDENTITY,
+    };
+
+    // None if the matrix can't be inverted
+    pub fn new(matrix: Mat4) -> Option<Self> {
+        Some(Self {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        Self {
+            matrix: Mat4::translation(offset),
+            inverse: Mat4::translation(-offset),
+        }
+    }
+
+    // factors must be non-zero
+    pub fn scale(factors: Vec3) -> Self {
+        assert!(factors.0 != 0.0 && factors.1 != 0.0 && factors.2 != 0.0);
+        Self {
+            matrix: Mat4::scaling(factors),
+            inverse: Mat4::scaling(Vec3(1.0 / factors.0, 1.0 / factors.1, 1.0 / factors.2)),
+        }
+    }
+
+    // rotation by angle (in radians) about axis, counterclockwise looking down the axis
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let axis = axis.as_unit();
+        Self {
+            matrix: Mat4::rotation(axis, angle),
+            // rotations are orthogonal
+            inverse: Mat4::rotation(axis, angle).transpose(),
+        }
+    }
+
+    // the rotation given by a quaternion
+    pub fn orient(rotation: Quaternion) -> Self {
+        Self {
+            matrix: rotation.matrix(),
+            inverse: rotation.matrix().transpose(),
+        }
+    }
+
+    // the transform applying self and then next
+    pub fn then(self, next: Transform) -> Self {
+        Self {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(self) -> Self {
+        Self {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn matrix(self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn point(self, point: Point3) -> Point3 {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn vector(self, vector: Vec3) -> Vec3 {
+        self.matrix.transform_vector(vector)
+    }
+
+    // Normals stay perpendicular to the surface under the inverse transpose. The result isn't
+    // normalized.
+    pub fn normal(self, normal: Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(normal)
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        v: Vec3::ZERO,
+    };
+
+    // rotation by angle (in radians) about axis, counterclockwise looking down the axis
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Self {
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Self {
+            w: cos,
+            v: sin * axis.as_unit(),
+        }
+    }
+
+    fn dot(self, rhs: Self) -> f64 {
+        self.w * rhs.w + self.v.dot(rhs.v)
+    }
+
+    // the angle (in radians) of the rotation taking self to rhs
+    pub fn angle_to(self, rhs: Self) -> f64 {
+        2.0 * self.dot(rhs).abs().min(1.0).acos()
+    }
+
+    // Spherical linear interpolation, turning at a constant rate along the shorter way from self
+    // (at t = 0) to rhs (at t = 1).
+    pub fn slerp(self, t: f64, rhs: Self) -> Self {
+        let mut cos = self.dot(rhs);
+        let mut rhs = rhs;
+        // q and -q are the same rotation
+        if cos < 0.0 {
+            cos = -cos;
+            rhs = Self {
+                w: -rhs.w,
+                v: -rhs.v,
+            };
+        }
+        let (a, b) = if cos > 0.9995 {
+            // nearly parallel, where linear interpolation is accurate and better conditioned
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        let w = a * self.w + b * rhs.w;
+        let v = a * self.v + b * rhs.v;
+        let norm = (w * w + v.length_sq()).sqrt();
+        Self {
+            w: w / norm,
+            v: v / norm,
+        }
+    }
+
+    pub fn matrix(self) -> Mat4 {
+        let Self {
+            w,
+            v: Vec3(x, y, z),
+        } = self;
+        Mat4([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
 impl Size {
     pub fn new(width: u32, height: u32) -> Self {
         Self { width, height }
@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::grid::VoxelGrid;
+use crate::hittable::*;
+use crate::material::*;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec::*;
+
+// A participating medium of varying density given by a voxel grid (e.g. the output of a smoke
+// simulation), filling the bounds of the grid. Like ConstantMedium, it scatters light according to
+// the phase function given as its material. Rays are traced through it by delta tracking, against
+// the largest density of the grid, and shadow rays estimate its transmittance by ratio tracking.
+pub struct Volume {
+    density: VoxelGrid,
+    // scales the values of the grid to the chance of scattering per unit distance
+    density_scale: f64,
+    // the largest density in the volume
+    majorant: f64,
+    phase_function: Arc<dyn Material>,
+    emission: Option<Emission>,
+}
+
+// Blackbody emission from a temperature grid spanning the same bounds as the density. The volume
+// emits in proportion to its density, so the empty parts of the grid stay dark however hot.
+pub struct Emission {
+    temperature: VoxelGrid,
+    // scales the values of the grid to kelvin
+    temperature_scale: f64,
+    intensity: f64,
+}
+
+// The phase function of a volume at a point where it glows
+struct EmittingPhase {
+    phase_function: Arc<dyn Material>,
+    radiance: Color,
+}
+
+impl Volume {
+    pub fn new(
+        density: VoxelGrid,
+        density_scale: f64,
+        phase_function: Arc<dyn Material>,
+        emission: Option<Emission>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            majorant: density_scale * density.max(),
+            density,
+            density_scale,
+            phase_function,
+            emission,
+        })
+    }
+
+    fn density_at(&self, point: Point3) -> f64 {
+        self.density_scale * self.density.lookup(point)
+    }
+
+    // Samples tentative collisions along the ray as if the volume had the majorant density
+    // throughout, calling visit with the density at each until it returns false. Returns the t of
+    // the last collision visited, or None if the ray left the volume.
+    fn track(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+        mut visit: impl FnMut(f64, &mut dyn Sampler) -> bool,
+    ) -> Option<f64> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let (mut t, t_end) = self.density.bounds().interval(ray, t_min, t_max)?;
+        let step = 1.0 / (self.majorant * ray.direction.length());
+        loop {
+            t -= (1.0 - sampler.get_1d()).ln() * step;
+            if t >= t_end {
+                return None;
+            }
+            if !visit(self.density_at(ray.at(t)), sampler) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Hittable for Volume {
+    // delta tracking: a tentative collision is a real one with the chance of the density there
+    // over the majorant, the others being null collisions that leave the ray as it is
+    fn hit(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<HitResult> {
+        let t = self.track(ray, t_min, t_max, sampler, |density, sampler| {
+            sampler.get_1d() * self.majorant >= density
+        })?;
+
+        let point = ray.at(t);
+        let radiance = self
+            .emission
+            .as_ref()
+            .map_or(Color::ZERO, |emission| emission.radiance(point));
+        let material: Arc<dyn Material> = if radiance.is_near_zero() {
+            Arc::clone(&self.phase_function)
+        } else {
+            Arc::new(EmittingPhase {
+                phase_function: Arc::clone(&self.phase_function),
+                radiance,
+            })
+        };
+        let record = HitRecord {
+            point,
+            t,
+            normal: -ray.direction.as_unit(),
+            front_face: true,
+            u: 0.0,
+            v: 0.0,
+        };
+        HitResult::new(record, material)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.density.bounds())
+    }
+
+    fn shadow_hit(&self, _: &Ray, _: f64, _: f64, _: &mut dyn Sampler) -> Option<HitResult> {
+        None
+    }
+
+    // ratio tracking: the product of the chances of each tentative collision being a null one,
+    // with Russian roulette once little light is left
+    fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> f64 {
+        let mut transmittance = 1.0;
+        self.track(ray, t_min, t_max, sampler, |density, sampler| {
+            transmittance *= 1.0 - density / self.majorant;
+            if transmittance < 0.1 {
+                if sampler.get_1d() < 0.5 {
+                    transmittance = 0.0;
+                    return false;
+                }
+                transmittance *= 2.0;
+            }
+            true
+        });
+        transmittance
+    }
+}
+
+impl Emission {
+    pub fn new(temperature: VoxelGrid, temperature_scale: f64, intensity: f64) -> Self {
+        Self {
+            temperature,
+            temperature_scale,
+            intensity,
+        }
+    }
+
+    fn radiance(&self, point: Point3) -> Color {
+        let temperature = self.temperature_scale * self.temperature.lookup(point);
+        self.intensity * blackbody(temperature)
+    }
+}
+
+impl Material for EmittingPhase {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResult {
+        self.phase_function.scatter(ray, hit, sampler)
+    }
+
+    fn eval(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> Color {
+        self.phase_function.eval(ray, hit, direction)
+    }
+
+    fn pdf(&self, ray: &Ray, hit: &HitRecord, direction: Vec3) -> f64 {
+        self.phase_function.pdf(ray, hit, direction)
+    }
+
+    fn emitted(&self, _: &Ray, _: &HitRecord) -> Color {
+        self.radiance
+    }
+}
+
+// The color (in linear Rec. 709) of a black body at a temperature in kelvin, with the spectrum
+// normalized to a peak of 1 so that only its shape changes with the temperature. Integrates
+// Planck's law against an analytic fit of the CIE 1931 color matching functions (Wyman et al.,
+// "Simple Analytic Approximations to the CIE XYZ Color Matching Functions").
+pub fn blackbody(temperature: f64) -> Color {
+    if temperature <= 0.0 {
+        return Color::ZERO;
+    }
+    const C: f64 = 299_792_458.0;
+    const H: f64 = 6.626_070_15e-34;
+    const K: f64 = 1.380_649e-23;
+    // the integral of the fitted y matching function over nm, the luminance of a flat spectrum
+    const CIE_Y_INTEGRAL: f64 = 106.923_069;
+    let planck = |lambda: f64| {
+        let lambda = lambda * 1e-9;
+        2.0 * H * C * C / (lambda.powi(5) * ((H * C / (lambda * K * temperature)).exp_m1()))
+    };
+    // Wien's displacement law gives the peak of the spectrum
+    let peak = planck(2.897_771_955e-3 / temperature * 1e9);
+
+    let lobe = |lambda: f64, mean: f64, below: f64, above: f64| {
+        let x = (lambda - mean) / if lambda < mean { below } else { above };
+        (-0.5 * x * x).exp()
+    };
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    const STEP: f64 = 5.0;
+    for i in 0..=94 {
+        let lambda = 360.0 + STEP * i as f64;
+        let power = planck(lambda) / peak * STEP;
+        x += power
+            * (1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+                - 0.065 * lobe(lambda, 501.1, 20.4, 26.2));
+        y += power
+            * (0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1));
+        z += power
+            * (1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8));
+    }
+    let (x, y, z) = (x / CIE_Y_INTEGRAL, y / CIE_Y_INTEGRAL, z / CIE_Y_INTEGRAL);
+
+    // XYZ to linear Rec. 709
+    let rgb = Color::new(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    );
+    Color::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0))
+}